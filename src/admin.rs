@@ -0,0 +1,186 @@
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Paragraph, Row, Table, TableState},
+};
+
+use crate::registry::SessionRegistry;
+
+const MAX_BROADCAST_LEN: usize = 200;
+
+// Dashboard scene for the admin user: every session, with keys to kick the selected one
+// or broadcast a notice to everyone.
+pub struct AdminPanel {
+    registry: SessionRegistry,
+    // Ends a session the same way the `kick ID` command does.
+    kick: Box<dyn Fn(usize) + Send + Sync>,
+    // The admin's own session, which can't be kicked from here.
+    own_id: usize,
+    selected: usize,
+    status: String,
+    broadcast_duration: Duration,
+    // The notice being typed, while in broadcast mode.
+    draft: Option<String>,
+}
+
+impl AdminPanel {
+    pub fn new(
+        registry: SessionRegistry,
+        kick: Box<dyn Fn(usize) + Send + Sync>,
+        own_id: usize,
+        broadcast_duration: Duration,
+    ) -> Self {
+        Self {
+            registry,
+            kick,
+            own_id,
+            selected: 0,
+            status: String::new(),
+            broadcast_duration,
+            draft: None,
+        }
+    }
+
+    pub fn is_typing(&self) -> bool {
+        self.draft.is_some()
+    }
+
+    pub fn handle_input(&mut self, data: &[u8]) {
+        if self.draft.is_some() {
+            self.edit_draft(data);
+            return;
+        }
+
+        match data {
+            b"k" | b"\x1b[A" | b"\x1bOA" => self.selected = self.selected.saturating_sub(1),
+            b"j" | b"\x1b[B" | b"\x1bOB" => self.selected += 1,
+            b"x" => self.kick_selected(),
+            b"b" => self.draft = Some(String::new()),
+            _ => {}
+        }
+    }
+
+    // Enter sends, Esc gives up, anything else edits the message.
+    fn edit_draft(&mut self, data: &[u8]) {
+        let Some(draft) = &mut self.draft else {
+            return;
+        };
+        if data == b"\x1b" {
+            self.draft = None;
+            return;
+        }
+        // Arrow keys and other escape sequences.
+        if data.starts_with(b"\x1b") {
+            return;
+        }
+
+        for c in String::from_utf8_lossy(data).chars() {
+            match c {
+                '\r' | '\n' => {
+                    let message = draft.trim().to_string();
+                    if !message.is_empty() {
+                        self.registry.broadcast(&message, self.broadcast_duration);
+                        self.status = "broadcast sent".to_string();
+                    }
+                    self.draft = None;
+                    return;
+                }
+                '\x7f' | '\x08' => {
+                    draft.pop();
+                }
+                c if !c.is_control() && draft.chars().count() < MAX_BROADCAST_LEN => {
+                    draft.push(c);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn kick_selected(&mut self) {
+        let Some(stats) = self.registry.list().into_iter().nth(self.selected) else {
+            return;
+        };
+        self.status = if stats.id == self.own_id {
+            "that's you, press q to leave instead".to_string()
+        } else {
+            (self.kick)(stats.id);
+            format!("kicked session {}", stats.id)
+        };
+    }
+
+    pub fn draw(&mut self, frame: &mut Frame) {
+        let sessions = self.registry.list();
+        self.selected = self.selected.min(sessions.len().saturating_sub(1));
+
+        let rows = sessions.iter().map(|stats| {
+            let peer = stats
+                .peer_addr
+                .map_or("-".to_string(), |addr| addr.to_string());
+            let id = if stats.id == self.own_id {
+                format!("{} (you)", stats.id)
+            } else {
+                stats.id.to_string()
+            };
+            Row::new([
+                id,
+                peer,
+                stats.user.clone(),
+                stats.caps().to_string(),
+                format_duration(stats.started_at.elapsed().as_secs()),
+                format_bytes(stats.bytes_sent.load(Ordering::Relaxed)),
+                format!("{:.1}", stats.fps()),
+            ])
+        });
+
+        let widths = [
+            Constraint::Length(9),
+            Constraint::Min(22),
+            Constraint::Length(12),
+            Constraint::Length(20),
+            Constraint::Length(9),
+            Constraint::Length(10),
+            Constraint::Length(5),
+        ];
+        let table = Table::new(rows, widths)
+            .header(
+                Row::new(["ID", "PEER", "USER", "TERM", "UPTIME", "SENT", "FPS"])
+                    .style(Style::default().add_modifier(Modifier::BOLD)),
+            )
+            .row_highlight_style(Style::default().bg(Color::Cyan).fg(Color::Black))
+            .block(Block::bordered().title(format!(" robert admin: {} sessions ", sessions.len())));
+
+        let [table_area, footer_area] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let mut state = TableState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, table_area, &mut state);
+
+        let footer = match &self.draft {
+            Some(draft) => Line::from(format!("broadcast: {draft}_  (enter send, esc cancel)")),
+            None => Line::from(format!(
+                "↑/↓ select  x kick  b broadcast  q quit  {}",
+                self.status
+            )),
+        };
+        frame.render_widget(
+            Paragraph::new(footer).style(Style::default().fg(Color::DarkGray)),
+            footer_area,
+        );
+    }
+}
+
+pub fn format_duration(secs: u64) -> String {
+    format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    match bytes {
+        0..1024 => format!("{bytes} B"),
+        1024..1_048_576 => format!("{:.1} KiB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MiB", bytes as f64 / 1_048_576.0),
+    }
+}
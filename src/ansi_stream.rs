@@ -0,0 +1,108 @@
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+
+use crate::input::{InputEvent, InputSource};
+
+// `nc` sends nothing until the user types, so a client that stays quiet this long is
+// taken to be one.
+const REQUEST_WAIT: std::time::Duration = std::time::Duration::from_millis(500);
+const MAX_REQUEST_LEN: usize = 8192;
+// Neither curl nor nc says how big the terminal is.
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+// A plain TCP client's side of the connection. The first read reports the size it
+// asked for (or the default); after that, whatever it types is passed on.
+pub struct Reader {
+    reader: OwnedReadHalf,
+    size: Option<(u16, u16)>,
+    buf: [u8; 1024],
+}
+
+impl InputSource for Reader {
+    async fn read(&mut self) -> Option<Vec<InputEvent>> {
+        if let Some((cols, rows)) = self.size.take() {
+            return Some(vec![InputEvent::Resize(cols, rows)]);
+        }
+        let n = self
+            .reader
+            .read(&mut self.buf)
+            .await
+            .ok()
+            .filter(|n| *n > 0)?;
+        Some(vec![InputEvent::Keys(self.buf[..n].to_vec())])
+    }
+}
+
+// Works out what kind of client connected. A `GET` gets an HTTP response header, so
+// curl streams the body, and its path can pick the size, as in `/?cols=120&rows=40`.
+// Anything else gets frames straight away. Also hands back the User-Agent, if any.
+pub async fn accept(
+    mut stream: TcpStream,
+) -> Result<Option<(Reader, OwnedWriteHalf, Option<String>)>, anyhow::Error> {
+    let mut start = [0u8; 4];
+    let is_http = match tokio::time::timeout(REQUEST_WAIT, stream.peek(&mut start)).await {
+        Ok(n) => &start[..n?] == b"GET ",
+        Err(_) => false,
+    };
+
+    let mut size = DEFAULT_SIZE;
+    let mut user_agent = None;
+    if is_http {
+        let mut request = Vec::new();
+        let mut buf = [0u8; 1024];
+        while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 || request.len() > MAX_REQUEST_LEN {
+                return Ok(None);
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+
+        let request = String::from_utf8_lossy(&request);
+        let mut lines = request.lines();
+        let path = lines
+            .next()
+            .and_then(|line| line.split(' ').nth(1))
+            .unwrap_or("/");
+        size = requested_size(path);
+        user_agent = lines.find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.eq_ignore_ascii_case("user-agent")
+                .then(|| value.trim().to_string())
+        });
+
+        stream
+            .write_all(
+                b"HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+            )
+            .await?;
+    }
+
+    let (reader, writer) = stream.into_split();
+    let reader = Reader {
+        reader,
+        size: Some(size),
+        buf: [0; 1024],
+    };
+    Ok(Some((reader, writer, user_agent)))
+}
+
+fn requested_size(path: &str) -> (u16, u16) {
+    let (mut cols, mut rows) = DEFAULT_SIZE;
+    let query = path.split_once('?').map_or("", |(_, query)| query);
+    for pair in query.split('&') {
+        let Some((key, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let Ok(value) = value.parse::<u16>() else {
+            continue;
+        };
+        match key {
+            "cols" if value > 0 => cols = value,
+            "rows" if value > 0 => rows = value,
+            _ => {}
+        }
+    }
+    (cols, rows)
+}
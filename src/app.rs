@@ -1,18 +1,61 @@
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
 
-use image::{ImageReader, Rgb};
-use ordered_float::OrderedFloat;
+use chrono::Local;
+use image::imageops::{self, FilterType};
+use image::{ImageFormat, ImageReader, Rgb, RgbaImage};
 use rand::{Rng, rngs::StdRng};
 use ratatui::{
     Frame,
-    style::Color,
+    buffer::Buffer,
+    layout::Rect,
+    layout::{Constraint, Flex, Layout},
+    style::{Color, Style},
     symbols::Marker,
-    widgets::canvas::{Canvas, Points},
+    text::Line,
+    widgets::{
+        Block, BorderType, Clear, Paragraph, Widget,
+        canvas::{Canvas, Points},
+    },
 };
 
-type PixelMap = HashMap<(OrderedFloat<f64>, OrderedFloat<f64>), Rgb<u8>>;
+use crate::admin::{AdminPanel, format_bytes, format_duration};
+use crate::caps::{CUBE_LEVELS, ColorDepth, Glyphs, ImageProtocol, distance};
+use crate::confetti::Celebration;
+use crate::config::{
+    Background, ClockConfig, Corner, DayNightConfig, Edge, MarqueeConfig, PhysicsConfig,
+    ScaredConfig,
+};
+use crate::corners::CornerCounter;
+use crate::flock::Flock;
+use crate::kitty;
+use crate::matrix::MatrixRain;
+use crate::mood::{Events, Feelings, Mood};
+use crate::particles::{Particle, Particles};
+use crate::pong::Pong;
+use crate::quips::Quips;
+use crate::registry::SessionStats;
+use crate::sixel;
+use crate::squash::Squash;
+use crate::status_bar::StatusBar;
+
+// Robert's sprite for each mood, in Mood::ALL order.
+pub type MoodSprites = [Arc<PixelMap>; Mood::ALL.len()];
+
+pub struct PixelMap {
+    // The sprite as loaded, for drawing it as an image or scaling it.
+    pub image: RgbaImage,
+    // Its opaque pixels grouped by color, so the canvas draws each color in one go.
+    // They're placed the way the canvas draws them: a cell per pixel across and half
+    // a cell per pixel down.
+    pub points: Vec<(Color, Vec<(f64, f64)>)>,
+    // The same, mirrored left to right, for when robert's heading left.
+    pub mirrored: Vec<(Color, Vec<(f64, f64)>)>,
+}
 
-pub fn load_to_pixel_map(file_name: &str) -> PixelMap {
+pub fn load_to_pixel_map(file_name: &str, chroma_key: Option<Rgb<u8>>) -> PixelMap {
     let open_expect = format!("Couldn't find {file_name}.");
     let decode_expect = format!("Couldn't decode {file_name}.");
 
@@ -20,103 +63,2227 @@ pub fn load_to_pixel_map(file_name: &str) -> PixelMap {
         .expect(&open_expect)
         .decode()
         .expect(&decode_expect);
-    let img_as_rgb = img.to_rgb8();
-
-    let pixel_map: PixelMap = img_as_rgb
-        .enumerate_pixels()
-        .map(|(x, y, rgb_val)| {
-            let x = f64::from(x);
-            let y = f64::from(y);
-            let offset = f64::from(y > 1.0) * 0.5;
-            let actual_y = y * offset;
+
+    let mut img = img.to_rgba8();
+    key_out(&mut img, chroma_key);
+    to_pixel_map(&shrink_to_fit(img))
+}
+
+// Makes every pixel of exactly the key color transparent, for art drawn on a flat
+// background instead of with an alpha channel.
+fn key_out(img: &mut RgbaImage, chroma_key: Option<Rgb<u8>>) {
+    let Some(key) = chroma_key else {
+        return;
+    };
+    for pixel in img.pixels_mut() {
+        if pixel.0[..3] == key.0 {
+            pixel[3] = 0;
+        }
+    }
+}
+
+// Largest sprite that still bounces inside the bounds `check_bounds` assumes.
+pub const MAX_SPRITE_SIZE: u32 = 32;
+
+// Ordered dithering thresholds, in sixteenths.
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+// Anything bigger than robert is brought down to MAX_SPRITE_SIZE. Bilinear, so detail
+// is averaged in rather than dropped, then dithered, since the smooth gradients that
+// leaves would band badly on 256-color terminals.
+fn shrink_to_fit(img: RgbaImage) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    if width <= MAX_SPRITE_SIZE && height <= MAX_SPRITE_SIZE {
+        return img;
+    }
+
+    let factor = f64::from(MAX_SPRITE_SIZE) / f64::from(width.max(height));
+    let width = (f64::from(width) * factor).round().max(1.0) as u32;
+    let height = (f64::from(height) * factor).round().max(1.0) as u32;
+    let mut small = imageops::resize(&img, width, height, FilterType::Triangle);
+    dither_to_cube(&mut small);
+    small
+}
+
+// Moves each channel onto one of the two xterm cube levels either side of it, picking
+// the upper one as often as the value is close to it.
+fn dither_to_cube(img: &mut RgbaImage) {
+    for (x, y, pixel) in img.enumerate_pixels_mut() {
+        let threshold = f64::from(BAYER_4X4[y as usize % 4][x as usize % 4]) / 16.0;
+        for channel in &mut pixel.0[..3] {
+            let upper = CUBE_LEVELS
+                .iter()
+                .position(|&level| level >= *channel)
+                .unwrap_or(CUBE_LEVELS.len() - 1);
+            if upper == 0 {
+                continue;
+            }
+            let (low, high) = (CUBE_LEVELS[upper - 1], CUBE_LEVELS[upper]);
+            let closeness = f64::from(*channel - low) / f64::from(high - low);
+            *channel = if closeness > threshold { high } else { low };
+        }
+    }
+}
+
+// Builds a pixel map from an uploaded PNG, refusing anything larger than robert.
+pub fn pixel_map_from_png(bytes: &[u8], chroma_key: Option<Rgb<u8>>) -> Result<PixelMap, String> {
+    let img = image::load_from_memory_with_format(bytes, ImageFormat::Png)
+        .map_err(|e| format!("not a valid PNG: {e}"))?;
+    if img.width() > MAX_SPRITE_SIZE || img.height() > MAX_SPRITE_SIZE {
+        return Err(format!(
+            "sprite is {}x{}, the limit is {MAX_SPRITE_SIZE}x{MAX_SPRITE_SIZE}",
+            img.width(),
+            img.height()
+        ));
+    }
+    let mut img = img.to_rgba8();
+    key_out(&mut img, chroma_key);
+    Ok(to_pixel_map(&img))
+}
+
+// Pixels at least this opaque are drawn; anything fainter is left out.
+const ALPHA_THRESHOLD: u8 = 128;
+
+pub fn to_pixel_map(img_as_rgba: &RgbaImage) -> PixelMap {
+    let mut points: Vec<(Color, Vec<(f64, f64)>)> = Vec::new();
+    let mut groups = HashMap::new();
+    for (x, y, rgba_val) in img_as_rgba.enumerate_pixels() {
+        if rgba_val[3] < ALPHA_THRESHOLD {
+            continue;
+        }
+        let x = f64::from(x);
+        let y = f64::from(y);
+        let offset = f64::from(y > 1.0) * 0.5;
+        let actual_y = y * offset;
+
+        let color = Color::Rgb(rgba_val[0], rgba_val[1], rgba_val[2]);
+        let group = *groups.entry(color).or_insert_with(|| {
+            points.push((color, Vec::new()));
+            points.len() - 1
+        });
+        points[group].1.push((x, actual_y));
+    }
+
+    let right = f64::from(img_as_rgba.width().saturating_sub(1));
+    let mirrored = points
+        .iter()
+        .map(|(color, pixels)| {
             (
-                (OrderedFloat(x), OrderedFloat(actual_y)),
-                rgb_val.to_owned(),
+                *color,
+                pixels.iter().map(|&(x, y)| (right - x, y)).collect(),
             )
         })
-        .collect::<Vec<((OrderedFloat<f64>, OrderedFloat<f64>), Rgb<u8>)>>() // convert to Vec<((f64, f64), Rgb<u8>)>
+        .collect();
+    PixelMap {
+        image: img_as_rgba.clone(),
+        points,
+        mirrored,
+    }
+}
+
+// A single still frame of the sprite as plain ANSI text, two pixel rows per line,
+// for clients that connected without a pty.
+pub fn ansi_snapshot(file_name: &str) -> String {
+    let Ok(img) = ImageReader::open(file_name).and_then(|reader| reader.with_guessed_format())
+    else {
+        return String::new();
+    };
+    let Ok(img) = img.decode() else {
+        return String::new();
+    };
+    let img = img.to_rgb8();
+
+    let mut out = String::new();
+    for y in (0..img.height()).step_by(2) {
+        for x in 0..img.width() {
+            let top = img.get_pixel(x, y);
+            let bottom = if y + 1 < img.height() {
+                img.get_pixel(x, y + 1)
+            } else {
+                top
+            };
+            out.push_str(&format!(
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m\u{2580}",
+                top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+            ));
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+// Shown instead of the animation while the server is at its session cap.
+pub fn draw_waiting_room(frame: &mut Frame, position: usize, waiting: usize) {
+    let text = vec![
+        Line::from("robert is busy right now"),
+        Line::from(""),
+        Line::from(format!("you are {position} of {waiting} in line")),
+        Line::from("you'll be let in when a slot frees up"),
+        Line::from("press q to leave"),
+    ];
+    draw_popup(frame, " waiting room ", text, Color::Yellow);
+}
+
+// Last frame a session sees when it hits its time limit.
+pub fn draw_goodbye(frame: &mut Frame) {
+    let text = vec![
+        Line::from("time's up, thanks for visiting robert"),
+        Line::from("feel free to reconnect"),
+    ];
+    draw_popup(frame, " goodbye ", text, Color::Cyan);
+}
+
+// Shown instead of the animation while the client's window is over the size limit.
+pub fn draw_too_large(frame: &mut Frame, max_cols: u16, max_rows: u16) {
+    let text = vec![
+        Line::from("your terminal is too large for robert"),
+        Line::from(format!("please shrink it to {max_cols}x{max_rows} or less")),
+    ];
+    draw_popup(frame, " terminal too large ", text, Color::Yellow);
+}
+
+// A server-wide notice drawn over whatever the session is showing.
+pub fn draw_broadcast(frame: &mut Frame, message: &str) {
+    draw_popup(
+        frame,
+        " notice ",
+        vec![Line::from(message.to_string())],
+        Color::Magenta,
+    );
+}
+
+// The sprites blown up or shrunk for one window size and zoom.
+pub struct ScaledSprites {
+    // In Mood::ALL order.
+    maps: [PixelMap; Mood::ALL.len()],
+    // The window and zoom they were scaled for, and the cells they take up.
+    window: (u16, u16),
+    zoom: u32,
+    size: (u16, u16),
+}
+
+// A sprite ready to go out: sixel data for sixel terminals, or for kitty, the
+// command that hands over the PNG as image `kitty_id`.
+fn encode_sprite(protocol: ImageProtocol, image: &RgbaImage, kitty_id: u32) -> Vec<u8> {
+    match protocol {
+        ImageProtocol::Sixel => {
+            sixel::encode(image.width(), image.height(), SIXEL_SCALE, |x, y| {
+                let pixel = image.get_pixel(x, y);
+                (pixel[3] >= ALPHA_THRESHOLD).then(|| Rgb([pixel[0], pixel[1], pixel[2]]))
+            })
+        }
+        ImageProtocol::Kitty => {
+            let mut png = std::io::Cursor::new(Vec::new());
+            if let Err(e) = image.write_to(&mut png, ImageFormat::Png) {
+                eprintln!("Failed to encode sprite: {e}");
+            }
+            kitty::transmit(kitty_id, png.get_ref())
+        }
+    }
+}
+
+// The configured marquee text, and how far it has scrolled.
+pub struct Marquee {
+    config: MarqueeConfig,
+    scroll: f64,
+}
+
+impl Marquee {
+    pub fn new(config: MarqueeConfig) -> Self {
+        Self {
+            config,
+            scroll: 0.0,
+        }
+    }
+
+    // Scrolls on by `seconds` worth, wrapping once the text has gone all the way round
+    // a row `width` cells wide.
+    fn update(&mut self, seconds: f64, width: u16) {
+        let period = (self.config.text.chars().count() + usize::from(width)) as f64;
+        if period > 0.0 {
+            self.scroll = (self.scroll + self.config.speed * seconds).rem_euclid(period);
+        }
+    }
+}
+
+// One row with the text coming in from the right edge, going off the left and then
+// coming round again once it's gone. Cells the text isn't over are left alone.
+impl Widget for &Marquee {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let text: Vec<char> = self.config.text.chars().collect();
+        let width = usize::from(area.width);
+        let period = text.len() + width;
+        let scroll = self.scroll as usize;
+        for (x, position) in area.columns().map(|column| column.x).zip(scroll..) {
+            let Some(index) = (position % period).checked_sub(width) else {
+                continue;
+            };
+            buf[(x, area.y)]
+                .set_char(text[index])
+                .set_style(Style::default().fg(Color::Black).bg(Color::Yellow));
+        }
+    }
+}
+
+// Fills `area` with the configured background, to be drawn over.
+fn paint_background(buffer: &mut Buffer, area: Rect, background: &Background) {
+    let rgb = |color: Rgb<u8>| Color::Rgb(color[0], color[1], color[2]);
+    for position in area.positions() {
+        let cell = &mut buffer[position];
+        match *background {
+            Background::Solid { color } => {
+                cell.set_bg(rgb(color));
+            }
+            Background::Gradient { top, bottom } => {
+                let t = f64::from(position.y - area.y) / f64::from(area.height.max(2) - 1);
+                let mix = |channel: usize| {
+                    let (a, b) = (f64::from(top[channel]), f64::from(bottom[channel]));
+                    (a + (b - a) * t).round() as u8
+                };
+                cell.set_bg(Color::Rgb(mix(0), mix(1), mix(2)));
+            }
+            Background::Starfield { sky, density } => {
+                cell.set_bg(rgb(sky));
+                // The same cells get stars every frame, so the sky holds still.
+                let hash = cell_hash(position.x, position.y);
+                if (hash % 10_000) as f64 / 10_000.0 < density {
+                    let (symbol, shade) = match (hash >> 16) % 4 {
+                        0 => ("*", 230),
+                        1 => ("+", 170),
+                        _ => (".", 120),
+                    };
+                    cell.set_symbol(symbol)
+                        .set_fg(Color::Rgb(shade, shade, shade));
+                }
+            }
+        }
+    }
+}
+
+// Takes the backgrounds in `area` toward the day or night tint, with the terminal's own
+// taken to be black.
+fn tint_background(buffer: &mut Buffer, area: Rect, tint: (Rgb<u8>, f64)) {
+    for position in area.positions() {
+        let cell = &mut buffer[position];
+        let bg = match cell.bg {
+            Color::Rgb(..) => cell.bg,
+            _ => Color::Rgb(0, 0, 0),
+        };
+        cell.set_bg(mix_toward(bg, tint));
+    }
+}
+
+// `color` taken the given share of the way toward the tint color.
+fn mix_toward(color: Color, (toward, amount): (Rgb<u8>, f64)) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+    let mix = |channel: u8, index: usize| {
+        (f64::from(channel) + (f64::from(toward[index]) - f64::from(channel)) * amount).round()
+            as u8
+    };
+    Color::Rgb(mix(r, 0), mix(g, 1), mix(b, 2))
+}
+
+fn cell_hash(x: u16, y: u16) -> u32 {
+    let mut hash = (u32::from(x) << 16 | u32::from(y)).wrapping_mul(0x9e37_79b9);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^ (hash >> 13)
+}
+
+// Copies what a canvas drew in `layer` onto `buffer`, leaving the cells it didn't
+// touch, and the backgrounds it didn't set, as they were.
+fn overlay(buffer: &mut Buffer, layer: &Buffer) {
+    for position in layer.area.positions() {
+        let drawn = &layer[position];
+        if drawn.symbol() == " " {
+            continue;
+        }
+        let cell = &mut buffer[position];
+        cell.set_symbol(drawn.symbol()).set_fg(drawn.fg);
+        if drawn.bg != Color::Reset {
+            cell.set_bg(drawn.bg);
+        }
+    }
+}
+
+// The cell, counting from zero, of the last SGR mouse report in `data`, or None if
+// `data` isn't mouse reports. A moving pointer sends several at once.
+fn last_pointer(data: &[u8]) -> Option<(u16, u16)> {
+    let text = std::str::from_utf8(data).ok()?.strip_prefix("\x1b[<")?;
+    let mut last = None;
+    for report in text.split("\x1b[<") {
+        let report = report.strip_suffix(['M', 'm'])?;
+        let mut fields = report.split(';').map(str::parse::<u16>);
+        let (_button, col, row) = (
+            fields.next()?.ok()?,
+            fields.next()?.ok()?,
+            fields.next()?.ok()?,
+        );
+        last = Some((col.saturating_sub(1), row.saturating_sub(1)));
+    }
+    last
+}
+
+// Takes `speed` toward `cruise`, keeping `keep` of whatever it's over by. Slower
+// speeds are left alone.
+fn drag(speed: f64, cruise: f64, keep: f64) -> f64 {
+    let excess = (speed.abs() - cruise).max(0.0);
+    speed.signum() * (speed.abs().min(cruise) + excess * keep)
+}
+
+// Turns an RGB color's hue by `degrees`, keeping its saturation and brightness.
+fn shift_hue(color: Color, degrees: f64) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+    let [r, g, b] = [r, g, b].map(|channel| f64::from(channel) / 255.0);
+    let max = r.max(g).max(b);
+    let delta = max - r.min(g).min(b);
+    if delta == 0.0 {
+        // Grays have no hue to turn.
+        return color;
+    }
+    let hue = if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+
+    let hue = (hue + degrees).rem_euclid(360.0) / 60.0;
+    let x = delta * (1.0 - (hue.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (delta, x, 0.0),
+        1 => (x, delta, 0.0),
+        2 => (0.0, delta, x),
+        3 => (0.0, x, delta),
+        4 => (x, 0.0, delta),
+        _ => (delta, 0.0, x),
+    };
+    let min = max - delta;
+    let channel = |value: f64| ((value + min) * 255.0).round() as u8;
+    Color::Rgb(channel(r), channel(g), channel(b))
+}
+
+// What high contrast draws robert in, against black.
+const HIGH_CONTRAST_PALETTE: [(u8, u8, u8); 7] = [
+    (0, 0, 0),
+    (255, 255, 255),
+    (255, 255, 0),
+    (0, 255, 255),
+    (255, 0, 255),
+    (255, 0, 0),
+    (0, 255, 0),
+];
+
+// The nearest color in HIGH_CONTRAST_PALETTE.
+fn to_high_contrast(color: Color) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+    HIGH_CONTRAST_PALETTE
         .into_iter()
-        .collect::<PixelMap>(); // convert to PixelMap
+        .min_by_key(|&palette| distance((r, g, b), palette))
+        .map_or(color, |(r, g, b)| Color::Rgb(r, g, b))
+}
 
-    pixel_map
+// The gray as bright as an RGB color looks.
+fn to_gray(color: Color) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+    let luma = (0.299 * f64::from(r) + 0.587 * f64::from(g) + 0.114 * f64::from(b)).round() as u8;
+    Color::Rgb(luma, luma, luma)
 }
 
-pub struct App {
+// A fixed, even spread of values in 0..1 over a sprite's pixels, for dissolving one
+// sprite into another.
+fn dither(x: f64, y: f64) -> f64 {
+    ((x * 12.9898 + y * 78.233).sin() * 43758.5453).rem_euclid(1.0)
+}
+
+// Robert's colors in `mood`: greener when he's dizzy, dimmed when he's sleepy and
+// warmed up toward gold when he's excited.
+fn mood_color(color: Color, mood: Mood) -> Color {
+    let Color::Rgb(r, g, b) = color else {
+        return color;
+    };
+    let mix = |channel: u8, toward: f64, amount: f64| {
+        (f64::from(channel) * (1.0 - amount) + toward * amount).round() as u8
+    };
+    match mood {
+        Mood::Calm | Mood::Scared => color,
+        Mood::Dizzy => shift_hue(color, 100.0),
+        Mood::Sleepy => Color::Rgb(mix(r, 0.0, 0.45), mix(g, 0.0, 0.45), mix(b, 0.0, 0.45)),
+        Mood::Excited => Color::Rgb(mix(r, 255.0, 0.35), mix(g, 210.0, 0.35), mix(b, 60.0, 0.35)),
+    }
+}
+
+// How far down robert slumps when he's sleepy, and how far in from each side he's
+// pulled when he's excited, as shares of his sprite. And how quickly his rows wave
+// back and forth, a row at a time, when he's dizzy.
+const SLUMP: f64 = 0.25;
+const STRETCH: f64 = 0.1;
+const WOBBLE: f64 = 0.7;
+
+// Robert in `mood`, drawn from his calm sprite for moods without a sprite of their own:
+// wobbling when he's dizzy, slumped when he's sleepy and drawn up tall when he's
+// excited, all in mood_color's colors.
+pub fn mood_sprite(calm: &RgbaImage, mood: Mood) -> RgbaImage {
+    let (width, height) = calm.dimensions();
+    let slump = (f64::from(height) * SLUMP) as u32;
+    let inset = (f64::from(width) * STRETCH) as u32;
+    let mut drawn = RgbaImage::new(width, height);
+    for (x, y, pixel) in drawn.enumerate_pixels_mut() {
+        // The pixel of the calm sprite that ends up here, if any.
+        let from = match mood {
+            Mood::Calm | Mood::Scared => Some((x, y)),
+            Mood::Dizzy => {
+                let shift = (f64::from(y) * WOBBLE).sin().round() as i32;
+                x.checked_add_signed(shift).map(|x| (x, y))
+            }
+            Mood::Sleepy => y
+                .checked_sub(slump)
+                .map(|below| (x, below * height / (height - slump))),
+            Mood::Excited => x
+                .checked_sub(inset)
+                .map(|across| (across * width / (width - 2 * inset), y)),
+        };
+        let Some(source) = from.and_then(|(x, y)| calm.get_pixel_checked(x, y)) else {
+            continue;
+        };
+        let [r, g, b, alpha] = source.0;
+        if let Color::Rgb(r, g, b) = mood_color(Color::Rgb(r, g, b), mood) {
+            pixel.0 = [r, g, b, alpha];
+        }
+    }
+    drawn
+}
+
+// Redraws a half block canvas in ASCII: `#` where both halves are lit, and `'` or `.`
+// where only the top or bottom is. The colors go with it, for terminals that have them.
+pub fn blocks_to_ascii(buffer: &mut Buffer, area: Rect) {
+    for position in area.positions() {
+        let cell = &mut buffer[position];
+        let symbol = match cell.symbol() {
+            "█" => "#",
+            // The canvas puts two different colors under an upper half block.
+            "▀" if cell.bg != Color::Reset => "#",
+            "▀" => "'",
+            "▄" => ".",
+            _ => continue,
+        };
+        cell.set_symbol(symbol).set_bg(Color::Reset);
+    }
+}
+
+fn quantize_colors(buffer: &mut Buffer, area: Rect, depth: ColorDepth) {
+    for position in area.positions() {
+        let cell = &mut buffer[position];
+        cell.fg = depth.quantize(cell.fg);
+        cell.bg = depth.quantize(cell.bg);
+    }
+}
+
+// A small label at the top middle of the window.
+fn draw_paused(frame: &mut Frame) {
+    const LABEL: &str = " paused ";
+    let area = frame.area();
+    let [area] = Layout::horizontal([Constraint::Length(LABEL.len() as u16)])
+        .flex(Flex::Center)
+        .areas(Rect {
+            height: area.height.min(1),
+            ..area
+        });
+    frame.render_widget(
+        Paragraph::new(LABEL).style(Style::default().fg(Color::Black).bg(Color::Yellow)),
+        area,
+    );
+}
+
+// A bordered, centered box sized to fit `text`.
+fn draw_popup(frame: &mut Frame, title: &str, text: Vec<Line>, color: Color) {
+    let width = text
+        .iter()
+        .map(|line| line.width())
+        .chain([title.len()])
+        .max()
+        .unwrap_or(0) as u16
+        + 4;
+    let height = text.len() as u16 + 2;
+    let popup = Paragraph::new(text)
+        .centered()
+        .block(Block::bordered().title(title.to_string()))
+        .style(Style::default().fg(color));
+
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(frame.area());
+    let [area] = Layout::horizontal([Constraint::Length(width)])
+        .flex(Flex::Center)
+        .areas(area);
+    frame.render_widget(Clear, area);
+    frame.render_widget(popup, area);
+}
+
+const LOGO: [&str; 5] = [
+    r"           _               _   ",
+    r" _ __ ___ | |__   ___ _ __| |_ ",
+    r"| '__/ _ \| '_ \ / _ \ '__| __|",
+    r"| | | (_) | |_) |  __/ |  | |_ ",
+    r"|_|  \___/|_.__/ \___|_|   \__|",
+];
+
+// The room robert takes up, in cells, unless he's scaled to the window. Anything
+// smaller gets a resize prompt instead.
+const SPRITE_COLS: u16 = 32;
+const SPRITE_ROWS: u16 = 16;
+
+// Sixel robert assumes cells of 8x16 pixels, which makes each sprite pixel, a cell
+// wide and half a cell tall, an 8x8 square.
+const SIXEL_SCALE: u32 = 8;
+
+// What the sprites are called on a kitty terminal.
+// Kitty images for each mood go from this id up, in Mood::ALL order.
+const KITTY_FIRST_ID: u32 = 1;
+// Robert goes scared above this many times the session's speed setting.
+const SCARED_SPEED: f64 = 2.0;
+// Nobody pressing anything for this long leaves him sleepy.
+const SLEEPY_AFTER: Duration = Duration::from_secs(45);
+
+// Speeds are tuned per frame at this rate, and scaled by how much time each update
+// covers.
+pub const BASE_FPS: u32 = 30;
+// Updates a second, whatever rate a session is drawn at.
+pub const TICK_RATE: u32 = 60;
+// The longest gap between updates that's made up for, so a session that stalls doesn't
+// find robert across the window when it wakes.
+const MAX_FRAME_GAP: Duration = Duration::from_millis(250);
+// What `f` cycles a session through.
+const FPS_CHOICES: [u32; 3] = [15, 30, 60];
+// What `z` cycles robert's size through.
+const ZOOM_CHOICES: [u32; 3] = [1, 2, 4];
+
+// How fast robert goes with reduced motion on, against his usual speed.
+pub const REDUCED_MOTION_SPEED: f64 = 0.5;
+// The most roberts a session can have, from --roberts or `n`.
+pub const MAX_ROBERTS: u32 = 16;
+
+// Gravity mode, in rows a frame at BASE_FPS: what robert's fall speeds up by every
+// frame, how fast a key throws him back up, and how slow a bounce off the floor has to
+// be for him to settle.
+const GRAVITY: f64 = 0.08;
+const LAUNCH_SPEED: f64 = 2.0;
+const REST_SPEED: f64 = 0.3;
+// How much of his speed robert keeps through a bounce under gravity.
+const BOUNCE_KEEP: f64 = 0.75;
+
+// Degrees a second the rainbow turns robert's hues.
+const RAINBOW_SPEED: f64 = 120.0;
+
+// Sparks thrown off a wall whenever robert bounces, and how long they last.
+const SPARKS: usize = 8;
+const SPARK_LIFETIME: Duration = Duration::from_millis(400);
+const SPARK_SYMBOLS: [char; 3] = ['.', '*', '\''];
+
+// A gust of wind blows robert sideways about this often, on average. It adds to his
+// speed across, in cells a frame at BASE_FPS, and drag takes it back off. The streaks
+// show which way it blew.
+const GUST_EVERY: Duration = Duration::from_secs(30);
+const GUST_STRENGTH: f64 = 4.0;
+const GUST_STREAKS: usize = 12;
+const GUST_LIFETIME: Duration = Duration::from_millis(900);
+// Seasonal bits drift across at about this many a second.
+const SEASON_RATE: f64 = 3.0;
+
+// Far more rarely, a robert blips out in a burst of static and turns up somewhere else
+// after BLIP_AWAY.
+const TELEPORT_EVERY: Duration = Duration::from_secs(180);
+const BLIP_AWAY: Duration = Duration::from_millis(400);
+const BLIP_SPECKS: usize = 16;
+const BLIP_LIFETIME: Duration = Duration::from_millis(500);
+const BLIP_SYMBOLS: [char; 4] = ['.', '*', '+', ':'];
+// Following the pointer, robert turns this much of the way to it each frame at 30 FPS,
+// and stops scared once it's within this many columns of his middle.
+const STEERING: f64 = 0.15;
+const POINTER_NEAR: f64 = 6.0;
+// `+` and `-` change robert's speed by this much of his usual, within SPEED_RANGE, and
+// the new speed is up for SPEED_SHOWN.
+const SPEED_STEP: f64 = 0.25;
+pub const SPEED_RANGE: std::ops::RangeInclusive<f64> = 0.25..=3.0;
+const SPEED_SHOWN: Duration = Duration::from_millis(1500);
+
+// How long the idle warning stays up before the session is closed.
+const IDLE_WARNING: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Scene {
+    Splash,
+    Bouncing,
+    Matrix,
+    Pong,
+    Flock,
+    Admin,
+}
+
+impl Scene {
+    // Scenes a login name can pick, as in `ssh matrix@host`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "robert" => Some(Scene::Splash),
+            "matrix" => Some(Scene::Matrix),
+            "pong" => Some(Scene::Pong),
+            "flock" => Some(Scene::Flock),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            // Admin access comes from the login, never from a saved profile.
+            Scene::Splash | Scene::Bouncing | Scene::Admin => "robert",
+            Scene::Matrix => "matrix",
+            Scene::Pong => "pong",
+            Scene::Flock => "flock",
+        }
+    }
+}
+
+// Something drifting across the bouncing screen in its months of the year.
+pub struct Season {
+    pub name: &'static str,
+    months: &'static [u32],
+    symbols: &'static [char],
+    // In place of `symbols`, for terminals limited to ASCII.
+    ascii_symbol: char,
+    color: Color,
+    // Cells a frame at 30 FPS, before a little sway. Falling bits start at the top and
+    // rising ones at the bottom.
+    velocity: (f64, f64),
+}
+
+// Every season there is. The config file's `season` picks one by name.
+pub const SEASONS: [Season; 2] = [
+    Season {
+        name: "snow",
+        months: &[12],
+        symbols: &['*', '.', '+'],
+        ascii_symbol: '*',
+        color: Color::Rgb(230, 240, 255),
+        velocity: (0.0, 0.15),
+    },
+    Season {
+        name: "hearts",
+        months: &[2],
+        symbols: &['♥'],
+        ascii_symbol: 'v',
+        color: Color::Rgb(255, 90, 140),
+        velocity: (0.0, -0.1),
+    },
+];
+
+impl Season {
+    // The season the config file names, or for `month` (1 to 12) when it doesn't. None
+    // for "none", or a month without one.
+    pub fn pick(name: Option<&str>, month: u32) -> Option<&'static Season> {
+        match name {
+            Some(name) => SEASONS.iter().find(|season| season.name == name),
+            None => SEASONS.iter().find(|season| season.months.contains(&month)),
+        }
+    }
+}
+
+// One robert and how he's moving. The offset is how far the canvas is shifted to put
+// him in place, so it and the speeds run the opposite way to robert himself.
+#[derive(Clone, Copy, Debug)]
+pub struct Sprite {
     pub offset: (f64, f64),
     pub sx: f64,
     pub sy: f64,
-    pub normal_pixel_map: PixelMap,
-    pub scared_pixel_map: PixelMap,
+    // Where he was before the last update, for drawing him between updates.
+    pub previous_offset: (f64, f64),
+    // Settled on the floor under gravity.
+    pub resting: bool,
+    // Too close to the mouse pointer he's following.
+    pub cornered: bool,
+    pub feelings: Feelings,
+    // Which way he's facing, which is the way he last headed across. The sprites are
+    // drawn as they are heading right and mirrored heading left.
+    pub facing_left: bool,
+    // Squashed by the last wall he hit, while it lasts.
+    pub squash: Option<Squash>,
+    // How much longer he's gone after blipping out. He's neither drawn nor moved
+    // until he's back.
+    pub away: Duration,
+}
+
+impl Sprite {
+    pub fn new(offset: (f64, f64), sx: f64, sy: f64) -> Self {
+        Self {
+            offset,
+            sx,
+            sy,
+            previous_offset: offset,
+            resting: false,
+            cornered: false,
+            feelings: Feelings::default(),
+            squash: None,
+            away: Duration::ZERO,
+            // The canvas moves the opposite way to robert.
+            facing_left: sx > 0.0,
+        }
+    }
+
+    pub fn mood(&self) -> Mood {
+        self.feelings.mood
+    }
+
+    // Where the `i`th robert starts: the first in the top left, the rest spread out
+    // from him.
+    pub fn start_offset(i: usize) -> (f64, f64) {
+        (-(i as f64) * 40.0, -(i as f64) * 10.0)
+    }
+}
+
+pub struct App {
+    // Set with --roberts; never empty.
+    pub sprites: Vec<Sprite>,
+    pub pixel_maps: MoodSprites,
     pub rng: StdRng,
+    pub scene: Scene,
+    pub idle_timeout: Option<Duration>,
+    pub last_input: Instant,
+    pub session_ttl: Option<Duration>,
+    pub started_at: Instant,
+    pub matrix: MatrixRain,
+    pub pong: Pong,
+    pub flock: Flock,
+    pub admin: Option<AdminPanel>,
+    pub fps: u32,
+    // Lowered by the server while the client can't keep up with `fps`.
+    pub fps_cap: u32,
+    // The cells robert bounces around in, as of the last frame drawn. None until then,
+    // and while the window is too small for him.
+    pub play_area: Option<Rect>,
+    // When the last update ran and how long it covered, for drawing between updates.
+    pub last_update: Option<(Instant, Duration)>,
+    // Picked from the terminal's capabilities; `b` switches between Braille and half
+    // blocks where both work.
+    pub glyphs: Glyphs,
+    // RGB colors are brought down to this before they go out.
+    pub color_depth: ColorDepth,
+    // Draw robert as an image instead of on the canvas, where the terminal can.
+    pub image_protocol: Option<ImageProtocol>,
+    // Where the image goes this frame, in cells, if it's being drawn.
+    pub image_at: Option<(u16, u16)>,
+    // The sprite for each mood encoded for `image_protocol`, in Mood::ALL order and
+    // then all again mirrored, made on first use.
+    pub image_sprites: Option<Vec<Vec<u8>>>,
+    // The kitty image on screen, if there is one.
+    pub kitty_placed: Option<u32>,
+    // Set with --sprite-fraction: robert is scaled to take up this much of the window.
+    pub sprite_fraction: Option<f64>,
+    // Set with --canvas-cols and --canvas-rows: the most room robert's given to bounce in.
+    pub canvas_limit: (u16, u16),
+    pub scaled_sprites: Option<ScaledSprites>,
+    // Changed with `z`: robert drawn this many times his size, on top of any fraction.
+    pub zoom: u32,
+    // From the config file's [background] section, painted behind robert.
+    pub background: Option<Background>,
+    // Times robert has gone straight into a corner this session.
+    pub corner_hits: u32,
+    // And across every session, for the HUD.
+    pub corner_counter: Arc<CornerCounter>,
+    // Confetti for the last corner hit, while it's still flying.
+    pub celebration: Option<Celebration>,
+    // Toggled with `s`.
+    pub show_stats: bool,
+    // What the server measures about this session, for the stats overlay.
+    pub session_stats: Option<Arc<SessionStats>>,
+    // Set when the config file turns the status bar on.
+    pub status_bar: Option<StatusBar>,
+    // From the config file's [clock] section.
+    pub clock: Option<ClockConfig>,
+    // Picked from SEASONS when the session starts.
+    pub season: Option<&'static Season>,
+    // And its [day_night] section, with the tint it gave for the last frame.
+    pub day_night: Option<DayNightConfig>,
+    pub tint: Option<(Rgb<u8>, f64)>,
+    pub marquee: Option<Marquee>,
+    pub quips: Option<Quips>,
+    // Sparks from the walls robert bounced off.
+    pub particles: Particles,
+    // When `r` turned on hue cycling, if it's on.
+    pub rainbow_since: Option<Instant>,
+    // Toggled with `g`, for monochrome terminals or anyone who'd rather not have the
+    // colors.
+    pub grayscale: bool,
+    // Starts as the config file's theme says; `h` switches it.
+    pub high_contrast: bool,
+    // For motion-sensitive viewers: robert keeps to a slow, steady speed, which also
+    // keeps him from getting scared, and hits come without sparks or confetti.
+    pub reduced_motion: bool,
+    // Set from the config file: ring the bell at every bounce. `bell_pending` holds a
+    // ring until the server sends the frame.
+    pub bell: bool,
+    pub bell_pending: bool,
+    // Toggled with `G`: robert falls, bounces lower each time, and comes to rest on the
+    // floor until a key throws him back up.
+    pub gravity: bool,
+    // From the config file's [physics] section.
+    pub physics: PhysicsConfig,
+    // And its [scared] section.
+    pub scared: ScaredConfig,
+    // Toggled with `p`: robert heads for the mouse pointer, which the terminal only
+    // reports while this is on. `mouse_pending` holds a switch until the server sends
+    // the frame.
+    pub follow_mouse: bool,
+    pub mouse_pending: bool,
+    // Where the pointer was last reported, in cells from the top left of the play area.
+    pub pointer: Option<(f64, f64)>,
+    // Changed with `+` and `-`: robert goes this many times his usual speed.
+    pub speed: f64,
+    // When that last changed, to show the new speed for a moment.
+    pub speed_changed: Option<Instant>,
+    // Toggled with space: robert and everything around him stay put. Frames that come
+    // out the same as the last aren't sent, so a paused session goes quiet.
+    pub paused: bool,
 }
 
 impl App {
-    pub fn draw(&mut self, frame: &mut Frame) {
-        let fa = frame.area();
+    // Moves the current scene on by `dt`.
+    pub fn update(&mut self, dt: Duration) {
+        let dt = dt.min(MAX_FRAME_GAP);
+        let seconds = dt.as_secs_f64();
+        let scale = seconds * f64::from(BASE_FPS);
+        match self.scene {
+            Scene::Bouncing => {
+                for sprite in &mut self.sprites {
+                    sprite.previous_offset = sprite.offset;
+                }
+                if self.paused {
+                    return;
+                }
+                if let Some(area) = self.play_area {
+                    self.step(f64::from(area.width), f64::from(area.height), scale);
+                }
+                self.last_update = Some((Instant::now(), dt));
+                self.particles.update(scale);
+                if let Some(area) = self.play_area
+                    && !self.reduced_motion
+                    && self.rng.gen_bool((SEASON_RATE * seconds).min(1.0))
+                {
+                    self.drift_season(area);
+                }
+                if let Some(marquee) = &mut self.marquee {
+                    marquee.update(seconds, self.play_area.map_or(0, |area| area.width));
+                }
+                if let Some(celebration) = &mut self.celebration {
+                    celebration.update(scale);
+                    if celebration.is_over() {
+                        self.celebration = None;
+                    }
+                }
+            }
+            Scene::Matrix => self.matrix.update(&mut self.rng, scale),
+            Scene::Pong => self.pong.update(&mut self.rng, scale),
+            Scene::Flock => self.flock.update(scale),
+            Scene::Splash | Scene::Admin => {}
+        }
+    }
+
+    // Draws the current scene as it stands, without moving anything on.
+    pub fn render(&mut self, frame: &mut Frame) {
+        self.image_at = None;
+        match self.scene {
+            Scene::Splash => self.draw_splash(frame),
+            Scene::Bouncing => self.draw_bouncing(frame),
+            Scene::Matrix => self.matrix.draw(frame, &mut self.rng),
+            Scene::Pong => self.pong.draw(frame, &mut self.rng),
+            Scene::Flock => self.flock.draw(
+                frame,
+                &mut self.rng,
+                &self.pixel_maps[Mood::Calm.index()],
+                self.glyphs,
+            ),
+            Scene::Admin => {
+                if let Some(admin) = &mut self.admin {
+                    admin.draw(frame);
+                }
+            }
+        }
+        if self.show_stats {
+            self.draw_stats(frame);
+        }
+        if self.scene == Scene::Bouncing
+            && self
+                .speed_changed
+                .is_some_and(|at| at.elapsed() < SPEED_SHOWN)
+        {
+            let text = vec![Line::from(format!("{}x", self.speed))];
+            draw_popup(frame, " speed ", text, Color::Cyan);
+        }
+        if self.paused && self.scene == Scene::Bouncing {
+            draw_paused(frame);
+        }
+        if self.color_depth < ColorDepth::TrueColor {
+            let area = frame.area();
+            quantize_colors(frame.buffer_mut(), area, self.color_depth);
+        }
+
+        if let Some(remaining) = self.idle_warning() {
+            let text = vec![
+                Line::from(format!("disconnecting in {}s", remaining.as_secs() + 1)),
+                Line::from("press any key to stay"),
+            ];
+            draw_popup(frame, " idle ", text, Color::Red);
+        }
+    }
+
+    pub fn set_sprites(&mut self, pixel_maps: MoodSprites) {
+        self.pixel_maps = pixel_maps;
+        self.image_sprites = None;
+        self.scaled_sprites = None;
+        self.flock.forget_sprite();
+    }
+
+    // The sprite robert's drawn from in `mood`, as it's drawn right now.
+    fn mood_map(&self, mood: Mood) -> &PixelMap {
+        match &self.scaled_sprites {
+            Some(scaled) => &scaled.maps[mood.index()],
+            None => &self.pixel_maps[mood.index()],
+        }
+    }
+
+    // Nearest-neighbor scales the sprites to `sprite_fraction` of a `cols` x `rows`
+    // window, keeping their shape, and then by the zoom. Only redone when the window
+    // or zoom changes.
+    fn fit_sprites(&mut self, cols: u16, rows: u16) {
+        if self.sprite_fraction.is_none() && self.zoom == 1 {
+            if self.scaled_sprites.take().is_some() {
+                self.image_sprites = None;
+            }
+            return;
+        }
+        if self
+            .scaled_sprites
+            .as_ref()
+            .is_some_and(|scaled| scaled.window == (cols, rows) && scaled.zoom == self.zoom)
+        {
+            return;
+        }
+
+        let (width, height) = self.pixel_maps[Mood::Calm.index()].image.dimensions();
+        if width == 0 || height == 0 {
+            return;
+        }
+        // Pixels are a cell wide and half a cell tall.
+        let fitted = self.sprite_fraction.map_or(1.0, |fraction| {
+            (fraction * f64::from(cols) / f64::from(width))
+                .min(fraction * f64::from(rows) * 2.0 / f64::from(height))
+        });
+        let factor = fitted * f64::from(self.zoom);
+        let scaled_width = (f64::from(width) * factor).round().max(1.0) as u32;
+        let scaled_height = (f64::from(height) * factor).round().max(1.0) as u32;
+        let scale = |image: &RgbaImage| {
+            to_pixel_map(&imageops::resize(
+                image,
+                scaled_width,
+                scaled_height,
+                FilterType::Nearest,
+            ))
+        };
+
+        self.scaled_sprites = Some(ScaledSprites {
+            maps: self.pixel_maps.each_ref().map(|map| scale(&map.image)),
+            window: (cols, rows),
+            zoom: self.zoom,
+            size: (scaled_width as u16, scaled_height.div_ceil(2) as u16),
+        });
+        self.image_sprites = None;
+    }
+
+    pub fn set_image_protocol(&mut self, protocol: Option<ImageProtocol>) {
+        if self.image_protocol != protocol {
+            self.image_protocol = protocol;
+            self.image_sprites = None;
+        }
+    }
+
+    // The escape sequence that puts this frame's image robert in place, or takes a
+    // kitty one down, once the rest of the frame has gone out.
+    pub fn image_frame(&mut self) -> Option<Vec<u8>> {
+        let protocol = self.image_protocol?;
+        let Some((col, row)) = self.image_at else {
+            return self.kitty_placed.take().map(kitty::hide);
+        };
+
+        // Only a lone robert is sent as an image.
+        // An image can't be blended, so it switches halfway through.
+        let sprite = &self.sprites[0];
+        let mood = match sprite.feelings.scared_share() {
+            Some(share) if share > 0.5 => Mood::Scared,
+            _ => sprite.mood(),
+        };
+        let (cols, rows) = self.sprite_size();
+        let fresh = self.image_sprites.is_none();
+        if fresh {
+            let encoded = [false, true]
+                .into_iter()
+                .flat_map(|mirrored| Mood::ALL.map(|mood| (mood, mirrored)))
+                .zip(KITTY_FIRST_ID..)
+                .map(|((mood, mirrored), id)| {
+                    let mut image = self.recolored(self.mood_map(mood));
+                    if mirrored {
+                        imageops::flip_horizontal_in_place(&mut image);
+                    }
+                    encode_sprite(protocol, &image, id)
+                })
+                .collect();
+            self.image_sprites = Some(encoded);
+        }
+        let images = self.image_sprites.as_ref()?;
+        let mut index = Mood::ALL.iter().position(|&each| each == mood)?;
+        if sprite.facing_left {
+            index += Mood::ALL.len();
+        }
+
+        // Saved and restored around it, since terminals disagree on where an image
+        // leaves the cursor.
+        let mut out = b"\x1b7".to_vec();
+        let at = format!("\x1b[{};{}H", row + 1, col + 1);
+        match protocol {
+            ImageProtocol::Sixel => {
+                // Transparent pixels would show the last frame's robert, so the
+                // cells are blanked first.
+                for line in 0..rows {
+                    out.extend(format!("\x1b[{};{}H\x1b[{cols}X", row + line + 1, col + 1).bytes());
+                }
+                out.extend(at.bytes());
+                out.extend_from_slice(&images[index]);
+            }
+            ImageProtocol::Kitty => {
+                // Kitty keeps them all; after that, only the placement changes.
+                if fresh {
+                    for image in images {
+                        out.extend_from_slice(image);
+                    }
+                }
+                let id = KITTY_FIRST_ID + index as u32;
+                if let Some(previous) = self.kitty_placed.replace(id)
+                    && previous != id
+                {
+                    out.extend(kitty::hide(previous));
+                }
+                out.extend(at.bytes());
+                out.extend(kitty::place(id, cols, rows));
+            }
+        }
+        out.extend_from_slice(b"\x1b8");
+        Some(out)
+    }
+
+    // Whether robert bounced since the last time this was asked.
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::take(&mut self.bell_pending)
+    }
+
+    // Whether mouse reporting should be switched on or off, if that's changed since
+    // the last time this was asked.
+    pub fn take_mouse_switch(&mut self) -> Option<bool> {
+        std::mem::take(&mut self.mouse_pending).then_some(self.follow_mouse)
+    }
+
+    // True while a scene is taking free text, so keys like `q` reach it instead.
+    pub fn is_typing(&self) -> bool {
+        self.admin.as_ref().is_some_and(AdminPanel::is_typing)
+    }
+
+    pub fn handle_input(&mut self, data: &[u8]) {
+        self.last_input = Instant::now();
+
+        // Mouse reports only move the pointer; they aren't key presses.
+        if let Some((col, row)) = last_pointer(data) {
+            if let Some(area) = self.play_area {
+                self.pointer = Some((
+                    f64::from(col) - f64::from(area.x),
+                    f64::from(row) - f64::from(area.y),
+                ));
+            }
+            return;
+        }
+        if data == b"f"
+            && matches!(
+                self.scene,
+                Scene::Bouncing | Scene::Matrix | Scene::Pong | Scene::Flock
+            )
+        {
+            self.cycle_fps();
+            return;
+        }
+        // Pong already has `s` for moving down.
+        if data == b"s" && matches!(self.scene, Scene::Bouncing | Scene::Matrix | Scene::Flock) {
+            self.show_stats = !self.show_stats;
+            return;
+        }
+        if data == b"r" && self.scene == Scene::Bouncing {
+            self.rainbow_since = match self.rainbow_since {
+                Some(_) => None,
+                None => Some(Instant::now()),
+            };
+            return;
+        }
+        if data == b"m" && self.scene == Scene::Bouncing {
+            self.toggle_reduced_motion();
+            return;
+        }
+        if data == b"h" && self.scene == Scene::Bouncing {
+            self.high_contrast = !self.high_contrast;
+            self.image_sprites = None;
+            return;
+        }
+        if data == b"g" && self.scene == Scene::Bouncing {
+            self.grayscale = !self.grayscale;
+            self.image_sprites = None;
+            return;
+        }
+        if data == b"z" && self.scene == Scene::Bouncing {
+            self.zoom = ZOOM_CHOICES
+                .into_iter()
+                .find(|&zoom| zoom > self.zoom)
+                .unwrap_or(ZOOM_CHOICES[0]);
+            return;
+        }
+        if data == b"G" && self.scene == Scene::Bouncing {
+            self.toggle_gravity();
+            return;
+        }
+        if (data == b"+" || data == b"-") && self.scene == Scene::Bouncing {
+            let step = if data == b"+" {
+                SPEED_STEP
+            } else {
+                -SPEED_STEP
+            };
+            self.change_speed(step);
+            return;
+        }
+        // `r` is already rainbow.
+        if data == b"R" && self.scene == Scene::Bouncing {
+            for sprite in &mut self.sprites {
+                sprite.sx = -sprite.sx;
+                sprite.sy = -sprite.sy;
+            }
+            return;
+        }
+        if data == b"0" && self.scene == Scene::Bouncing {
+            self.reset_positions();
+            return;
+        }
+        // `m` is already reduced motion.
+        if data == b"n" && self.scene == Scene::Bouncing {
+            self.add_robert();
+            return;
+        }
+        if data == b"N" && self.scene == Scene::Bouncing {
+            if self.sprites.len() > 1 {
+                self.sprites.pop();
+            }
+            return;
+        }
+        if data == b" " && self.scene == Scene::Bouncing {
+            self.paused = !self.paused;
+            return;
+        }
+        if data == b"p" && self.scene == Scene::Bouncing {
+            self.toggle_follow_mouse();
+            return;
+        }
+        if data == b"b" && self.scene == Scene::Bouncing {
+            self.glyphs = match self.glyphs {
+                Glyphs::Braille => Glyphs::HalfBlock,
+                Glyphs::HalfBlock => Glyphs::Braille,
+                glyphs => glyphs,
+            };
+            return;
+        }
+
+        match self.scene {
+            Scene::Splash => self.scene = Scene::Bouncing,
+            Scene::Pong => self.pong.handle_input(data),
+            Scene::Admin => {
+                if let Some(admin) = &mut self.admin {
+                    admin.handle_input(data);
+                }
+            }
+            Scene::Bouncing if self.sprites.iter().any(|sprite| sprite.resting) => self.launch(),
+            Scene::Bouncing | Scene::Matrix | Scene::Flock => {}
+        }
+    }
+
+    pub fn effective_fps(&self) -> u32 {
+        self.fps.min(self.fps_cap)
+    }
+
+    pub fn frame_interval(&self) -> Duration {
+        Duration::from_secs(1) / self.effective_fps()
+    }
+
+    // The next of FPS_CHOICES above the current rate, wrapping around.
+    fn cycle_fps(&mut self) {
+        self.fps = FPS_CHOICES
+            .into_iter()
+            .find(|&fps| fps > self.fps)
+            .unwrap_or(FPS_CHOICES[0]);
+    }
+
+    // Time left before an idle disconnect, once the warning period has started.
+    fn idle_warning(&self) -> Option<Duration> {
+        let idle_timeout = self.idle_timeout?;
+        let idle = self.last_input.elapsed();
+        if idle < idle_timeout {
+            return None;
+        }
+        Some((idle_timeout + IDLE_WARNING).saturating_sub(idle))
+    }
+
+    pub fn is_idle_expired(&self) -> bool {
+        self.idle_timeout
+            .is_some_and(|idle_timeout| self.last_input.elapsed() >= idle_timeout + IDLE_WARNING)
+    }
+
+    pub fn is_ttl_expired(&self) -> bool {
+        self.session_ttl
+            .is_some_and(|ttl| self.started_at.elapsed() >= ttl)
+    }
+
+    // Numbers for debugging a slow client, in the top left.
+    fn draw_stats(&self, frame: &mut Frame) {
+        let area = frame.area();
+        let mut text = Vec::new();
+        if let Some(stats) = &self.session_stats {
+            text.extend([
+                Line::from(format!(
+                    "fps      {:.1} / {}",
+                    stats.fps(),
+                    self.effective_fps()
+                )),
+                Line::from(format!(
+                    "frame    {:.1} ms",
+                    stats.frame_time().as_secs_f64() * 1000.0
+                )),
+                Line::from(format!(
+                    "sent     {}",
+                    format_bytes(stats.bytes_sent.load(Ordering::Relaxed))
+                )),
+                Line::from(format!(
+                    "uptime   {}",
+                    format_duration(stats.started_at.elapsed().as_secs())
+                )),
+            ]);
+        }
+        text.extend([
+            Line::from(format!("size     {}x{}", area.width, area.height)),
+            // sx and sy are how far the canvas moves, the opposite way to robert.
+            Line::from(format!(
+                "velocity {:.1}, {:.1}",
+                -self.sprites[0].sx, -self.sprites[0].sy
+            )),
+        ]);
+
+        let width = text.iter().map(Line::width).max().unwrap_or(0) as u16 + 4;
+        let height = text.len() as u16 + 2;
+        let stats_area = Rect::new(area.x, area.y, width, height).intersection(area);
+        frame.render_widget(Clear, stats_area);
+        frame.render_widget(
+            Paragraph::new(text)
+                .block(Block::bordered().title(" stats "))
+                .style(Style::default().fg(Color::White).bg(Color::Black)),
+            stats_area,
+        );
+    }
+
+    fn draw_splash(&self, frame: &mut Frame) {
+        let mut text: Vec<Line> = LOGO.iter().map(|row| Line::from(*row)).collect();
+        text.extend([
+            Line::from(""),
+            Line::from("q  quit"),
+            Line::from("f  frame rate (15/30/60)"),
+            Line::from("b  braille mode"),
+            Line::from("s  stats"),
+            Line::from("r  rainbow"),
+            Line::from("g  grayscale"),
+            Line::from("h  high contrast"),
+            Line::from("m  reduced motion"),
+            Line::from("z  zoom (1x/2x/4x)"),
+            Line::from("G  gravity"),
+            Line::from("p  follow the mouse"),
+            Line::from("+  faster"),
+            Line::from("-  slower"),
+            Line::from("space  pause"),
+            Line::from("R  reverse"),
+            Line::from("0  back to the start"),
+            Line::from("n  another robert"),
+            Line::from("N  one less robert"),
+            Line::from(""),
+            Line::from("press any key to continue"),
+        ]);
+        let height = text.len() as u16;
+        let splash = Paragraph::new(text)
+            .centered()
+            .style(Style::default().fg(Color::Cyan));
+
+        let [area] = Layout::vertical([Constraint::Length(height)])
+            .flex(Flex::Center)
+            .areas(frame.area());
+        frame.render_widget(splash, area);
+    }
+
+    // Where to draw robert: on from where the last update found him toward where it
+    // left him, as far as the next update is along. Frames that land between updates
+    // then move him as smoothly as the updates do, a tick behind.
+    fn drawn_offset(&self, sprite: &Sprite) -> (f64, f64) {
+        let Some((at, covered)) = self.last_update else {
+            return sprite.offset;
+        };
+        if covered.is_zero() {
+            return sprite.offset;
+        }
+        let along = (at.elapsed().as_secs_f64() / covered.as_secs_f64()).min(1.0);
+        let (from, to) = (sprite.previous_offset, sprite.offset);
+        (
+            from.0 + (to.0 - from.0) * along,
+            from.1 + (to.1 - from.1) * along,
+        )
+    }
+
+    // The room robert takes up with the current glyphs, in cells.
+    fn sprite_size(&self) -> (u16, u16) {
+        let (cols, rows) = self
+            .scaled_sprites
+            .as_ref()
+            .map_or((SPRITE_COLS, SPRITE_ROWS), |scaled| scaled.size);
+        // Braille packs 2x4 dots into a cell where half blocks fit 1x2, so robert
+        // needs half the room in each direction.
+        if self.image_protocol.is_none() && self.glyphs == Glyphs::Braille {
+            return (cols.div_ceil(2), rows.div_ceil(2));
+        }
+        (cols, rows)
+    }
+
+    fn draw_bouncing(&mut self, frame: &mut Frame) {
+        let mut fa = frame.area();
+        if let Some(status_bar) = &self.status_bar {
+            let [canvas_area, bar_area] =
+                Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(fa);
+            status_bar.draw(frame, bar_area);
+            fa = canvas_area;
+        }
+        let (max_cols, max_rows) = self.canvas_limit;
+        let [fa] = Layout::horizontal([Constraint::Max(max_cols)])
+            .flex(Flex::Center)
+            .areas(fa);
+        let [fa] = Layout::vertical([Constraint::Max(max_rows)])
+            .flex(Flex::Center)
+            .areas(fa);
+        self.fit_sprites(fa.width, fa.height);
+        let (sprite_cols, sprite_rows) = self.sprite_size();
+        if fa.width < sprite_cols || fa.height < sprite_rows {
+            self.play_area = None;
+            let text = vec![
+                Line::from("resize to at least"),
+                Line::from(format!("{sprite_cols}x{sprite_rows}")),
+            ];
+            draw_popup(frame, " too small ", text, Color::Yellow);
+            return;
+        }
+
         let width = f64::from(fa.width);
         let height = f64::from(fa.height);
+        self.play_area = Some(fa);
 
-        self.check_bounds(width, height);
-        self.offset.0 += self.sx;
-        self.offset.1 += self.sy;
+        // High contrast is left as it is, whatever the time.
+        let tint = self
+            .day_night
+            .filter(|_| !self.high_contrast)
+            .and_then(|day_night| day_night.tint(Local::now().time()));
+        if tint != self.tint {
+            self.tint = tint;
+            self.image_sprites = None;
+        }
+        let background = self.current_background();
+        if let Some(background) = &background {
+            paint_background(frame.buffer_mut(), fa, background);
+        }
+        if let Some(tint) = tint {
+            tint_background(frame.buffer_mut(), fa, tint);
+        }
+
+        // The image goes out after the frame, so its cells are left for it, which also
+        // gets them cleared once it moves on. Not while the idle warning is up, which
+        // would end up underneath, or while the colors are changing every frame. Only
+        // for a lone robert, since there's one image on screen, and not while he's blipped
+        // out.
+        if self.image_protocol.is_some()
+            && self.sprites.len() == 1
+            && self.sprites[0].away.is_zero()
+            && self.idle_warning().is_none()
+            && self.rainbow_since.is_none()
+        {
+            let (x, y) = self.drawn_offset(&self.sprites[0]);
+            let col = fa.x + (-x).round() as u16;
+            let row = fa.y + (-y).round() as u16;
+            let sprite = Rect::new(col, row, sprite_cols, sprite_rows).intersection(fa);
+            for position in sprite.positions() {
+                frame.buffer_mut()[position].set_skip(true);
+            }
+            self.image_at = Some((col, row));
+            self.particles.draw(frame.buffer_mut(), fa);
+            self.draw_quip(frame, fa);
+            self.draw_marquee(frame, fa);
+            self.draw_clock(frame, fa);
+            self.draw_corner_hud(frame, fa);
+            self.draw_celebration(frame, fa);
+            return;
+        }
 
+        // Pixel maps are laid out for half blocks: a cell per pixel across, half a
+        // cell per pixel down. Braille dots are half that again both ways, and whole
+        // blocks just lose every other row. ASCII is drawn as half blocks and then
+        // spelled out.
+        let (marker, pixel_scale) = match self.glyphs {
+            Glyphs::Braille => (Marker::Braille, 0.5),
+            Glyphs::HalfBlock | Glyphs::Ascii => (Marker::HalfBlock, 1.0),
+            Glyphs::Block => (Marker::Block, 1.0),
+        };
+        let ascii = self.glyphs == Glyphs::Ascii;
         let canvas = Canvas::default()
-            .marker(Marker::HalfBlock)
+            .marker(marker)
             .x_bounds([0.0, width])
             .y_bounds([0.0, height])
             .paint(|ctx| {
-                let current_map = if self.is_scared() {
-                    &self.scared_pixel_map
-                } else {
-                    &self.normal_pixel_map
-                };
-                for (coord, rv) in current_map {
-                    let x = coord.0;
-                    let y = coord.1;
-                    let px_offset = self.offset.0;
-                    let py_offset = self.offset.1;
+                let mut coords = Vec::new();
+                for sprite in self.sprites.iter().filter(|sprite| sprite.away.is_zero()) {
+                    let (px_offset, py_offset) = self.drawn_offset(sprite);
+                    // Blending back from being scared, the pixels dithered under the
+                    // scared share come from the scared sprite.
+                    let share = sprite.feelings.scared_share();
+                    let layers = [
+                        Some((sprite.mood(), share.unwrap_or(0.0)..1.0)),
+                        share.map(|share| (Mood::Scared, 0.0..share)),
+                    ];
+                    for (mood, shown) in layers.into_iter().flatten() {
+                        let map = self.mood_map(mood);
+                        let points = if sprite.facing_left {
+                            &map.mirrored
+                        } else {
+                            &map.points
+                        };
+                        let size = (
+                            f64::from(map.image.width()),
+                            f64::from(map.image.height()) * 0.5,
+                        );
+                        for (color, pixels) in points {
+                            coords.clear();
+                            let pixels = pixels
+                                .iter()
+                                .filter(|&&(x, y)| shown.contains(&dither(x, y)));
+                            for &(x, y) in pixels {
+                                let mut place = |x: f64, y: f64| {
+                                    coords.push((
+                                        x * pixel_scale - px_offset,
+                                        height - y * pixel_scale + py_offset,
+                                    ));
+                                };
+                                match &sprite.squash {
+                                    Some(squash) => squash.cover((x, y), size, place),
+                                    None => place(x, y),
+                                }
+                            }
+                            ctx.draw(&Points {
+                                coords: &coords,
+                                color: self.sprite_color(*color),
+                            });
+                        }
+                    }
+                }
+            });
+        if background.is_some() || tint.is_some() {
+            // The canvas blanks the backgrounds of its whole area, so it's drawn on its
+            // own and laid over the top.
+            let mut layer = Buffer::empty(fa);
+            canvas.render(fa, &mut layer);
+            if ascii {
+                blocks_to_ascii(&mut layer, fa);
+            }
+            overlay(frame.buffer_mut(), &layer);
+        } else {
+            frame.render_widget(canvas, fa);
+            if ascii {
+                blocks_to_ascii(frame.buffer_mut(), fa);
+            }
+        }
+        self.particles.draw(frame.buffer_mut(), fa);
+        self.draw_quip(frame, fa);
+        self.draw_marquee(frame, fa);
+        self.draw_clock(frame, fa);
+        self.draw_corner_hud(frame, fa);
+        self.draw_celebration(frame, fa);
+    }
 
-                    ctx.draw(&Points {
-                        coords: &[(*x - px_offset, height - *y + py_offset)],
-                        color: Color::Rgb(rv[0], rv[1], rv[2]),
-                    });
+    // A speech bubble over the first robert's head, or under his feet when he's at the
+    // top, while he has something to say.
+    fn draw_quip(&mut self, frame: &mut Frame, area: Rect) {
+        let Some(quips) = &mut self.quips else {
+            return;
+        };
+        let Some(message) = quips.current(&mut self.rng) else {
+            return;
+        };
+        let text = Line::from(message.to_string());
+        let width = (text.width() as u16 + 4).min(area.width);
+        let (_, sprite_rows) = self.sprite_size();
+        let (x, y) = self.drawn_offset(&self.sprites[0]);
+        let col = area.x + (-x).round() as u16;
+        let row = area.y + (-y).round() as u16;
+        let y = if row >= area.y + 3 {
+            row - 3
+        } else {
+            row + sprite_rows
+        };
+        let x = col.min(area.right().saturating_sub(width));
+        let bubble = Rect::new(x, y, width, 3).intersection(area);
+        frame.render_widget(Clear, bubble);
+        frame.render_widget(
+            Paragraph::new(text)
+                .centered()
+                .block(Block::bordered().border_type(BorderType::Rounded))
+                .style(Style::default().fg(Color::Black).bg(Color::White)),
+            bubble,
+        );
+    }
+
+    // The marquee along its edge of `area`.
+    fn draw_marquee(&self, frame: &mut Frame, area: Rect) {
+        let Some(marquee) = &self.marquee else {
+            return;
+        };
+        if area.height == 0 {
+            return;
+        }
+        let row = match marquee.config.position {
+            Edge::Top => area.y,
+            Edge::Bottom => area.bottom() - 1,
+        };
+        frame.render_widget(marquee, Rect::new(area.x, row, area.width, 1));
+    }
+
+    // The configured clock, in its corner of `area`.
+    fn draw_clock(&self, frame: &mut Frame, area: Rect) {
+        let Some(clock) = &self.clock else {
+            return;
+        };
+        let text = format!(" {} ", Local::now().format(&clock.format));
+        let width = Line::from(text.as_str()).width() as u16;
+        if width > area.width || area.height == 0 {
+            return;
+        }
+        let (x, y) = match clock.position {
+            Corner::TopLeft => (area.x, area.y),
+            Corner::TopRight => (area.right() - width, area.y),
+            Corner::BottomLeft => (area.x, area.bottom() - 1),
+            Corner::BottomRight => (area.right() - width, area.bottom() - 1),
+        };
+        frame.buffer_mut().set_string(
+            x,
+            y,
+            text,
+            Style::default().fg(Color::Black).bg(Color::Gray),
+        );
+    }
+
+    // High contrast puts robert on black, whatever the config says.
+    fn current_background(&self) -> Option<Background> {
+        if self.high_contrast {
+            Some(Background::Solid {
+                color: Rgb([0, 0, 0]),
+            })
+        } else {
+            self.background
+        }
+    }
+
+    // The last stage a sprite color goes through before it's drawn.
+    fn sprite_color(&self, color: Color) -> Color {
+        let color = match self.rainbow_since {
+            Some(since) => shift_hue(color, since.elapsed().as_secs_f64() * RAINBOW_SPEED),
+            None => color,
+        };
+        let color = match self.tint {
+            Some(tint) => mix_toward(color, tint),
+            None => color,
+        };
+        let color = if self.high_contrast {
+            to_high_contrast(color)
+        } else {
+            color
+        };
+        if self.grayscale {
+            to_gray(color)
+        } else {
+            color
+        }
+    }
+
+    // A sprite's image with its colors put through `sprite_color`, for sending as an
+    // image.
+    fn recolored(&self, pixel_map: &PixelMap) -> RgbaImage {
+        let mut image = pixel_map.image.clone();
+        for pixel in image.pixels_mut() {
+            let color = Color::Rgb(pixel[0], pixel[1], pixel[2]);
+            if let Color::Rgb(r, g, b) = self.sprite_color(color) {
+                pixel.0 = [r, g, b, pixel[3]];
+            }
+        }
+        image
+    }
+
+    // The corner hit counts, tucked into the bottom right of `area`.
+    fn draw_corner_hud(&self, frame: &mut Frame, area: Rect) {
+        let text = format!(
+            " corners {} | all time {} ",
+            self.corner_hits,
+            self.corner_counter.total()
+        );
+        let width = text.len() as u16;
+        if width > area.width || area.height == 0 {
+            return;
+        }
+        frame.buffer_mut().set_string(
+            area.right() - width,
+            area.bottom() - 1,
+            text,
+            Style::default().fg(Color::Black).bg(Color::Gray),
+        );
+    }
+
+    fn draw_celebration(&self, frame: &mut Frame, area: Rect) {
+        if let Some(celebration) = &self.celebration {
+            celebration.draw(frame, area);
+        }
+    }
+
+    // Moves every robert on inside a `width` x `height` cell area, `scale` frames'
+    // worth at BASE_FPS.
+    fn step(&mut self, width: f64, height: f64, scale: f64) {
+        for i in 0..self.sprites.len() {
+            let mut sprite = self.sprites[i];
+            self.step_sprite(&mut sprite, width, height, scale);
+            self.sprites[i] = sprite;
+        }
+        self.collide();
+
+        let seconds = scale / f64::from(BASE_FPS);
+        let calm = self.reduced_motion || self.sprites.iter().all(|sprite| sprite.resting);
+        if !calm
+            && self
+                .rng
+                .gen_bool((seconds / GUST_EVERY.as_secs_f64()).min(1.0))
+        {
+            self.gust(width, height);
+        }
+        if !calm
+            && self
+                .rng
+                .gen_bool((seconds / TELEPORT_EVERY.as_secs_f64()).min(1.0))
+        {
+            self.teleport(width, height);
+        }
+    }
+
+    fn step_sprite(&mut self, sprite: &mut Sprite, width: f64, height: f64, scale: f64) {
+        let dt = Duration::from_secs_f64(scale / f64::from(BASE_FPS));
+        if !sprite.away.is_zero() {
+            sprite.away = sprite.away.saturating_sub(dt);
+            if sprite.away.is_zero() {
+                self.blip(sprite);
+                sprite.feelings.dizzy();
+            }
+            return;
+        }
+        // Kept inside the walls, which also brings him straight back into view after
+        // the window shrinks.
+        let (sprite_cols, sprite_rows) = self.sprite_size();
+        let max_x = (width - f64::from(sprite_cols)).max(0.0);
+        let max_y = (height - f64::from(sprite_rows)).max(0.0);
+        sprite.offset.0 = sprite.offset.0.clamp(-max_x, 0.0);
+        sprite.offset.1 = sprite.offset.1.clamp(-max_y, 0.0);
+        if sprite.resting {
+            sprite.offset.1 = -max_y;
+        }
+        let (sx, sy) = (sprite.sx, sprite.sy);
+
+        if let Some(squash) = &mut sprite.squash
+            && !squash.update(dt)
+        {
+            sprite.squash = None;
+        }
+        let corner_hit = self.check_bounds(sprite, width, height);
+        if let Some(pointer) = self.pointer.filter(|_| self.follow_mouse) {
+            self.follow(sprite, pointer, width, height, scale);
+        } else if self.gravity && !sprite.resting {
+            sprite.sy -= GRAVITY * scale;
+        } else if !self.gravity {
+            // What's left of a burst after this step's share of drag.
+            let keep = (1.0 - self.physics.drag).powf(scale / f64::from(BASE_FPS));
+            let (cruise_x, cruise_y) = self.cruise_speed();
+            sprite.sx = drag(sprite.sx, cruise_x, keep);
+            sprite.sy = drag(sprite.sy, cruise_y, keep);
+        }
+        sprite.offset.0 += sprite.sx * scale;
+        sprite.offset.1 += sprite.sy * scale;
+        if sprite.sx != 0.0 {
+            sprite.facing_left = sprite.sx > 0.0;
+        }
+
+        // Speeding up past SCARED_SPEED is the fright, not staying over it. Reduced motion
+        // skips frights altogether, since being scared is a flash of a different sprite.
+        let fast = |sx: f64, sy: f64| sx.abs().max(sy.abs()) > SCARED_SPEED * self.speed;
+        let events = Events {
+            corner_hit,
+            fright: !self.reduced_motion
+                && (sprite.cornered || (!fast(sx, sy) && fast(sprite.sx, sprite.sy))),
+            drowsy: sprite.resting || self.last_input.elapsed() >= SLEEPY_AFTER,
+        };
+        sprite.feelings.update(dt, events, &self.scared);
+    }
+
+    // Turns robert toward `pointer`, at his usual speed, until it gets close enough to
+    // scare him to a stop. He heads for the nearest spot he can reach when it's past a
+    // wall.
+    fn follow(
+        &self,
+        sprite: &mut Sprite,
+        pointer: (f64, f64),
+        width: f64,
+        height: f64,
+        scale: f64,
+    ) {
+        let (sprite_cols, sprite_rows) = self.sprite_size();
+        let (half_cols, half_rows) = (f64::from(sprite_cols) / 2.0, f64::from(sprite_rows) / 2.0);
+        let middle = (-sprite.offset.0 + half_cols, -sprite.offset.1 + half_rows);
+        // Rows count double, since they're twice as tall.
+        let near = (pointer.0 - middle.0).hypot((pointer.1 - middle.1) * 2.0);
+        sprite.cornered = near < POINTER_NEAR;
+
+        let target = (
+            pointer
+                .0
+                .clamp(half_cols, (width - half_cols).max(half_cols)),
+            pointer
+                .1
+                .clamp(half_rows, (height - half_rows).max(half_rows)),
+        );
+        let (dx, dy) = (target.0 - middle.0, (target.1 - middle.1) * 2.0);
+        let distance = dx.hypot(dy);
+        let (cruise, _) = self.cruise_speed();
+        // The canvas moves the opposite way to robert.
+        let wanted = if sprite.cornered || distance < 1.0 {
+            (0.0, 0.0)
+        } else {
+            (-dx / distance * cruise, -dy / distance * cruise / 2.0)
+        };
+        let turn = (STEERING * scale).min(1.0);
+        sprite.sx += (wanted.0 - sprite.sx) * turn;
+        sprite.sy += (wanted.1 - sprite.sy) * turn;
+    }
+
+    // Roberts bump like billiard balls of the same weight: two that overlap and are
+    // still closing swap speeds along the side they overlap least on.
+    fn collide(&mut self) {
+        let (sprite_cols, sprite_rows) = self.sprite_size();
+        let (width, height) = (f64::from(sprite_cols), f64::from(sprite_rows));
+        for i in 0..self.sprites.len() {
+            for j in i + 1..self.sprites.len() {
+                let (a, b) = (self.sprites[i], self.sprites[j]);
+                if !a.away.is_zero() || !b.away.is_zero() {
+                    continue;
+                }
+                let dx = b.offset.0 - a.offset.0;
+                let dy = b.offset.1 - a.offset.1;
+                // As a share of robert's size, since rows are taller than columns.
+                let overlap_x = 1.0 - dx.abs() / width;
+                let overlap_y = 1.0 - dy.abs() / height;
+                if overlap_x <= 0.0 || overlap_y <= 0.0 {
+                    continue;
                 }
+                // Offsets and speeds both run the opposite way to robert, so the
+                // signs cancel out.
+                let (first, second) = self.sprites.split_at_mut(j);
+                let (a, b) = (&mut first[i], &mut second[0]);
+                if overlap_x < overlap_y {
+                    if dx * (b.sx - a.sx) >= 0.0 {
+                        continue;
+                    }
+                    std::mem::swap(&mut a.sx, &mut b.sx);
+                } else {
+                    if dy * (b.sy - a.sy) >= 0.0 {
+                        continue;
+                    }
+                    std::mem::swap(&mut a.sy, &mut b.sy);
+                }
+                a.resting = false;
+                b.resting = false;
+            }
+        }
+    }
+
+    // One line of the `robert` subsystem stream, for the first robert. Positions are in
+    // cells from the top-left.
+    pub fn state_json(&self, frame: u64) -> String {
+        let sprite = &self.sprites[0];
+        format!(
+            "{{\"frame\":{},\"x\":{:.2},\"y\":{:.2},\"sx\":{:.2},\"sy\":{:.2},\"scared\":{},\"mood\":\"{}\"}}\n",
+            frame,
+            -sprite.offset.0,
+            -sprite.offset.1,
+            sprite.sx,
+            sprite.sy,
+            sprite.mood() == Mood::Scared,
+            sprite.mood().name()
+        )
+    }
+
+    // Turns robert around at a wall, unless he's already heading away from it. True if
+    // he went straight into a corner.
+    fn check_bounds(&mut self, sprite: &mut Sprite, width: f64, height: f64) -> bool {
+        let (sprite_cols, sprite_rows) = self.sprite_size();
+        let max_x = (width - f64::from(sprite_cols)).max(0.0);
+        let max_y = (height - f64::from(sprite_rows)).max(0.0);
+        let hit_y = (sprite.offset.1 >= 0.0 && sprite.sy > 0.0)
+            || (sprite.offset.1 <= -max_y && sprite.sy < 0.0);
+        let hit_x = (sprite.offset.0 >= 0.0 && sprite.sx > 0.0)
+            || (sprite.offset.0 <= -max_x && sprite.sx < 0.0);
+        if hit_x && hit_y {
+            // Heading up and left means the top left corner, and so on.
+            let corner = (
+                if sprite.sx > 0.0 { 0.0 } else { width },
+                if sprite.sy > 0.0 { 0.0 } else { height },
+            );
+            self.corner_hits += 1;
+            self.corner_counter.hit();
+            if !self.reduced_motion {
+                self.celebration = Some(Celebration::new(&mut self.rng, corner, self.corner_hits));
+            }
+        }
+        // Sparks fly off the middle of whichever side touched.
+        let (left, top) = (-sprite.offset.0, -sprite.offset.1);
+        let sparks = !self.reduced_motion;
+        self.bell_pending |= self.bell && (hit_x || hit_y);
+        // Heading up or left means it's his top or left side that hit.
+        if !self.reduced_motion && (hit_x || hit_y) {
+            sprite.squash = Some(if hit_x {
+                Squash::new(true, sprite.sx > 0.0)
+            } else {
+                Squash::new(false, sprite.sy > 0.0)
             });
-        frame.render_widget(canvas, frame.area());
+        }
+        if hit_y {
+            let y = if sprite.sy > 0.0 { 0.0 } else { height - 1.0 };
+            let x = left + f64::from(sprite_cols) / 2.0;
+            if sparks {
+                self.spark((x, y), (0.0, sprite.sy.signum()));
+            }
+            self.reverse_sy(sprite);
+        }
+        if hit_x {
+            let x = if sprite.sx > 0.0 { 0.0 } else { width - 1.0 };
+            let y = top + f64::from(sprite_rows) / 2.0;
+            if sparks {
+                self.spark((x, y), (sprite.sx.signum(), 0.0));
+            }
+            self.reverse_sx(sprite);
+        }
+        // Off the floor, which leaves him heading up, he also loses speed across it.
+        if self.gravity && hit_y && sprite.sy > 0.0 {
+            sprite.sx *= BOUNCE_KEEP;
+            if sprite.sy < REST_SPEED {
+                sprite.sx = 0.0;
+                sprite.sy = 0.0;
+                sprite.resting = true;
+            }
+        }
+        hit_x && hit_y
     }
-    fn check_bounds(&mut self, width: f64, height: f64) {
-        if self.offset.1 > 0.0 {
-            self.reverse_sy();
+
+    // Blows every robert in the air left or right, with streaks across the window going
+    // the same way.
+    fn gust(&mut self, width: f64, height: f64) {
+        let direction = if self.rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+        for sprite in self.sprites.iter_mut().filter(|sprite| !sprite.resting) {
+            // sx is how far the canvas moves, the opposite way to robert.
+            sprite.sx -= direction * GUST_STRENGTH;
         }
-        if self.offset.1 < -(height - 16.0) {
-            self.reverse_sy();
+        let start = if direction > 0.0 { 0.0 } else { width - 1.0 };
+        for _ in 0..GUST_STREAKS {
+            self.particles.spawn(Particle {
+                position: (start, self.rng.gen_range(0.0..height.max(1.0))),
+                velocity: (direction * self.rng.gen_range(2.0..4.0), 0.0),
+                gravity: 0.0,
+                lifetime: GUST_LIFETIME,
+                color: Color::Rgb(200, 220, 255),
+                symbol: '~',
+            });
         }
-        if self.offset.0 < -(width - 32.0) {
-            self.reverse_sx();
+    }
+
+    // Picks a robert in the air to blip out, and where he'll turn up again, anywhere he
+    // fits.
+    fn teleport(&mut self, width: f64, height: f64) {
+        let candidates: Vec<usize> = (0..self.sprites.len())
+            .filter(|&i| !self.sprites[i].resting && self.sprites[i].away.is_zero())
+            .collect();
+        if candidates.is_empty() {
+            return;
         }
-        if self.offset.0 > 0.0 {
-            self.reverse_sx();
+        let i = candidates[self.rng.gen_range(0..candidates.len())];
+        let mut sprite = self.sprites[i];
+        self.blip(&sprite);
+
+        let (sprite_cols, sprite_rows) = self.sprite_size();
+        let max_x = (width - f64::from(sprite_cols)).max(0.0);
+        let max_y = (height - f64::from(sprite_rows)).max(0.0);
+        sprite.offset = (
+            -self.rng.gen_range(0.0..=max_x),
+            -self.rng.gen_range(0.0..=max_y),
+        );
+        // Straight there, rather than drawn sliding across.
+        sprite.previous_offset = sprite.offset;
+        sprite.away = BLIP_AWAY;
+        self.sprites[i] = sprite;
+    }
+
+    // One more bit of the season, if there is one, somewhere along the top or bottom
+    // of `area`, lasting until it's gone past the other side.
+    fn drift_season(&mut self, area: Rect) {
+        let Some(season) = self.season else {
+            return;
+        };
+        let symbol = if self.glyphs == Glyphs::Ascii {
+            season.ascii_symbol
+        } else {
+            season.symbols[self.rng.gen_range(0..season.symbols.len())]
+        };
+        let (width, height) = (f64::from(area.width), f64::from(area.height));
+        let (vx, vy) = season.velocity;
+        let y = if vy < 0.0 { height - 1.0 } else { 0.0 };
+        let crossing = height / vy.abs().max(0.01) / f64::from(BASE_FPS);
+        self.particles.spawn(Particle {
+            position: (self.rng.gen_range(0.0..width.max(1.0)), y),
+            velocity: (vx + self.rng.gen_range(-0.05..0.05), vy),
+            gravity: 0.0,
+            lifetime: Duration::from_secs_f64(crossing),
+            color: season.color,
+            symbol,
+        });
+    }
+
+    // Static over where `sprite` is, as he goes or comes back.
+    fn blip(&mut self, sprite: &Sprite) {
+        let (sprite_cols, sprite_rows) = self.sprite_size();
+        let (left, top) = (-sprite.offset.0, -sprite.offset.1);
+        for _ in 0..BLIP_SPECKS {
+            let position = (
+                left + self.rng.gen_range(0.0..f64::from(sprite_cols.max(1))),
+                top + self.rng.gen_range(0.0..f64::from(sprite_rows.max(1))),
+            );
+            let shade = self.rng.gen_range(160..=255);
+            self.particles.spawn(Particle {
+                position,
+                velocity: (
+                    self.rng.gen_range(-0.3..0.3),
+                    self.rng.gen_range(-0.15..0.15),
+                ),
+                gravity: 0.0,
+                lifetime: BLIP_LIFETIME,
+                color: Color::Rgb(shade, shade, 255),
+                symbol: BLIP_SYMBOLS[self.rng.gen_range(0..BLIP_SYMBOLS.len())],
+            });
         }
     }
-    fn generate_magnitude(&mut self, default: f64, is_x: bool) -> f64 {
-        let odds = if is_x { 1.0 / 2.0 } else { 1.0 / 5.0 };
-        let crazy_value = if is_x { 20.0 } else { 5.0 };
+
+    // A burst of sparks from `at` on a wall, thrown out along `away`, which points off
+    // it into the window.
+    fn spark(&mut self, at: (f64, f64), away: (f64, f64)) {
+        for _ in 0..SPARKS {
+            let speed = self.rng.gen_range(0.3..1.2);
+            let spread = self.rng.gen_range(-0.8..0.8);
+            let velocity = (
+                away.0 * speed + away.1.abs() * spread,
+                away.1 * speed + away.0.abs() * spread,
+            );
+            self.particles.spawn(Particle {
+                position: at,
+                // Rows are about twice as tall as columns are wide.
+                velocity: (velocity.0, velocity.1 * 0.5),
+                gravity: 0.0,
+                lifetime: SPARK_LIFETIME,
+                color: Color::Rgb(255, 220, 140),
+                symbol: SPARK_SYMBOLS[self.rng.gen_range(0..SPARK_SYMBOLS.len())],
+            });
+        }
+    }
+    // His cruising speed off a wall, or now and then a burst, with the odds and sizes
+    // the [physics] section gives.
+    fn generate_magnitude(&mut self, is_x: bool) -> f64 {
+        let (cruise_x, cruise_y) = self.cruise_speed();
+        if self.reduced_motion {
+            return if is_x { cruise_x } else { cruise_y };
+        }
+        let physics = self.physics;
+        let (cruise, odds, burst) = if is_x {
+            (cruise_x, physics.burst_odds_x, physics.burst_x)
+        } else {
+            (cruise_y, physics.burst_odds_y, physics.burst_y)
+        };
         if self.rng.gen_range(0.0..1.0) < odds {
-            crazy_value
+            burst * self.speed
         } else {
-            default
+            cruise
+        }
+    }
+    fn reverse_sy(&mut self, sprite: &mut Sprite) {
+        if self.gravity {
+            sprite.sy = -sprite.sy * BOUNCE_KEEP;
+            return;
+        }
+        let magnitude = self.generate_magnitude(false);
+        sprite.sy = -sprite.sy.signum() * magnitude;
+    }
+    fn reverse_sx(&mut self, sprite: &mut Sprite) {
+        if self.gravity {
+            sprite.sx = -sprite.sx * BOUNCE_KEEP;
+            return;
+        }
+        let magnitude = self.generate_magnitude(true);
+        sprite.sx = -sprite.sx.signum() * magnitude;
+    }
+
+    // Every robert speeds up or slows down straight away, bursts included.
+    fn change_speed(&mut self, step: f64) {
+        let speed = (self.speed + step).clamp(*SPEED_RANGE.start(), *SPEED_RANGE.end());
+        let ratio = speed / self.speed;
+        for sprite in &mut self.sprites {
+            sprite.sx *= ratio;
+            sprite.sy *= ratio;
+        }
+        self.speed = speed;
+        self.speed_changed = Some(Instant::now());
+    }
+
+    // Straight to the new speed rather than at the next wall.
+    fn toggle_reduced_motion(&mut self) {
+        self.reduced_motion = !self.reduced_motion;
+        // Under gravity, his speed is whatever the fall has made it.
+        if !self.gravity {
+            self.reset_speed();
         }
     }
-    fn reverse_sy(&mut self) {
-        let magnitude = self.generate_magnitude(1.0, false);
-        self.sy = -self.sy.signum() * magnitude;
+
+    // Turning gravity off puts him back to his usual speeds.
+    fn toggle_gravity(&mut self) {
+        self.gravity = !self.gravity;
+        for sprite in &mut self.sprites {
+            sprite.resting = false;
+        }
+        if !self.gravity {
+            self.reset_speed();
+        }
     }
-    fn reverse_sx(&mut self) {
-        let magnitude = self.generate_magnitude(1.5, true);
-        self.sx = -self.sx.signum() * magnitude;
+
+    // Turning it off sends robert on his way again at his usual speeds.
+    fn toggle_follow_mouse(&mut self) {
+        self.follow_mouse = !self.follow_mouse;
+        self.mouse_pending = true;
+        self.pointer = None;
+        for sprite in &mut self.sprites {
+            sprite.cornered = false;
+            if !self.follow_mouse && sprite.sx == 0.0 {
+                sprite.sx = if self.rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+            }
+            if !self.follow_mouse && sprite.sy == 0.0 {
+                sprite.sy = if self.rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+            }
+        }
+        if !self.follow_mouse {
+            self.reset_speed();
+        }
     }
 
-    fn is_scared(&self) -> bool {
-        self.sx.abs() > 2.0 || self.sy.abs() > 2.0
+    // Every robert on the floor goes back up, heading either way.
+    fn launch(&mut self) {
+        let (cruise, _) = self.cruise_speed();
+        for sprite in self.sprites.iter_mut().filter(|sprite| sprite.resting) {
+            sprite.resting = false;
+            sprite.sy = LAUNCH_SPEED;
+            sprite.sx = if self.rng.gen_bool(0.5) {
+                cruise
+            } else {
+                -cruise
+            };
+        }
+    }
+
+    // One more robert where the next would have started, heading any which way, up to
+    // MAX_ROBERTS.
+    fn add_robert(&mut self) {
+        if self.sprites.len() >= MAX_ROBERTS as usize {
+            return;
+        }
+        let (cruise_x, cruise_y) = self.cruise_speed();
+        let mut heading = || if self.rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+        let (sx, sy) = (heading() * cruise_x, heading() * cruise_y);
+        let offset = Sprite::start_offset(self.sprites.len());
+        self.sprites.push(Sprite::new(offset, sx, sy));
+    }
+
+    // Every robert back where the session started them, at their usual speeds: the
+    // first in the top left heading down and right, the rest spread out from him,
+    // keeping their headings. For when a resize leaves one stuck.
+    fn reset_positions(&mut self) {
+        let (cruise_x, cruise_y) = self.cruise_speed();
+        for (i, sprite) in self.sprites.iter_mut().enumerate() {
+            let (sx, sy) = if i == 0 {
+                (-cruise_x, -cruise_y)
+            } else {
+                (sprite.sx.signum() * cruise_x, sprite.sy.signum() * cruise_y)
+            };
+            *sprite = Sprite::new(Sprite::start_offset(i), sx, sy);
+        }
+    }
+
+    // Their usual speeds for the current motion setting, keeping their headings.
+    fn reset_speed(&mut self) {
+        let (cruise_x, cruise_y) = self.cruise_speed();
+        for sprite in &mut self.sprites {
+            sprite.sx = sprite.sx.signum() * cruise_x;
+            sprite.sy = sprite.sy.signum() * cruise_y;
+        }
+    }
+
+    // How fast robert goes across and down between bursts.
+    fn cruise_speed(&self) -> (f64, f64) {
+        let speed = if self.reduced_motion {
+            REDUCED_MOTION_SPEED
+        } else {
+            1.0
+        };
+        let physics = self.physics;
+        (
+            physics.cruise_x * speed * self.speed,
+            physics.cruise_y * speed * self.speed,
+        )
     }
 }
@@ -0,0 +1,84 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+// One JSON object per line, appended to the file at AUDIT_LOG_LOCATION. Every connection
+// gets a connect line when it's accepted and a disconnect line when it goes, whether or
+// not it ever got in; SSH logins that do get in have an auth line in between.
+pub struct AuditLog {
+    file: Mutex<File>,
+}
+
+// Fields that don't apply to an event are left out of its line.
+#[derive(Serialize)]
+pub struct AuditEvent {
+    pub ts: f64,
+    pub event: &'static str,
+    pub session: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub term: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_secs: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_sent: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bytes_received: Option<u64>,
+}
+
+impl AuditEvent {
+    pub fn new(event: &'static str, session: usize) -> Self {
+        let ts = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0.0, |since_epoch| since_epoch.as_secs_f64());
+        Self {
+            ts,
+            event,
+            session,
+            peer: None,
+            user: None,
+            client: None,
+            term: None,
+            width: None,
+            height: None,
+            duration_secs: None,
+            bytes_sent: None,
+            bytes_received: None,
+        }
+    }
+}
+
+impl AuditLog {
+    pub fn open(path: &Path) -> Result<Self, anyhow::Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn record(&self, event: &AuditEvent) {
+        let line = match serde_json::to_string(event) {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!("Failed to encode audit event: {e}");
+                return;
+            }
+        };
+        if let Err(e) = writeln!(self.file.lock().unwrap(), "{line}") {
+            eprintln!("Failed to write audit log: {e}");
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+// fail2ban-style bookkeeping: recent connects and auth failures per IP, and who is banned.
+pub struct BanTracker {
+    max_connections_per_minute: Option<usize>,
+    max_auth_failures: Option<usize>,
+    ban_duration: Duration,
+    connections: HashMap<IpAddr, VecDeque<Instant>>,
+    auth_failures: HashMap<IpAddr, VecDeque<Instant>>,
+    banned_until: HashMap<IpAddr, Instant>,
+}
+
+impl BanTracker {
+    pub fn new(
+        max_connections_per_minute: Option<usize>,
+        max_auth_failures: Option<usize>,
+        ban_duration: Duration,
+    ) -> Self {
+        Self {
+            max_connections_per_minute,
+            max_auth_failures,
+            ban_duration,
+            connections: HashMap::new(),
+            auth_failures: HashMap::new(),
+            banned_until: HashMap::new(),
+        }
+    }
+
+    pub fn is_banned(&self, ip: IpAddr) -> bool {
+        self.banned_until
+            .get(&ip)
+            .is_some_and(|until| *until > Instant::now())
+    }
+
+    pub fn record_connection(&mut self, ip: IpAddr) {
+        let Some(max) = self.max_connections_per_minute else {
+            return;
+        };
+        if Self::record(&mut self.connections, ip) > max {
+            self.ban(ip, "too many connections");
+        }
+    }
+
+    pub fn record_auth_failure(&mut self, ip: IpAddr) {
+        let Some(max) = self.max_auth_failures else {
+            return;
+        };
+        if Self::record(&mut self.auth_failures, ip) > max {
+            self.ban(ip, "too many failed logins");
+        }
+    }
+
+    // Forgets events older than the window and lifts bans that have run out.
+    pub fn expire(&mut self) {
+        let now = Instant::now();
+        for events in [&mut self.connections, &mut self.auth_failures] {
+            events.retain(|_, times| {
+                while times.front().is_some_and(|t| now - *t > WINDOW) {
+                    times.pop_front();
+                }
+                !times.is_empty()
+            });
+        }
+        self.banned_until.retain(|ip, until| {
+            let active = *until > now;
+            if !active {
+                println!("Ban on {ip} expired");
+            }
+            active
+        });
+    }
+
+    fn record(events: &mut HashMap<IpAddr, VecDeque<Instant>>, ip: IpAddr) -> usize {
+        let now = Instant::now();
+        let times = events.entry(ip).or_default();
+        while times.front().is_some_and(|t| now - *t > WINDOW) {
+            times.pop_front();
+        }
+        times.push_back(now);
+        times.len()
+    }
+
+    fn ban(&mut self, ip: IpAddr, reason: &str) {
+        if !self.is_banned(ip) {
+            println!(
+                "Banning {ip} for {}s: {reason}",
+                self.ban_duration.as_secs()
+            );
+        }
+        self.banned_until
+            .insert(ip, Instant::now() + self.ban_duration);
+        self.connections.remove(&ip);
+        self.auth_failures.remove(&ip);
+    }
+}
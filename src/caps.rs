@@ -0,0 +1,506 @@
+use std::fmt;
+
+use ratatui::style::Color;
+
+// How many colors a client's terminal can show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    Mono,
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+// The xterm defaults for the 16 ANSI colors, in index order.
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::Red, (205, 0, 0)),
+    (Color::Green, (0, 205, 0)),
+    (Color::Yellow, (205, 205, 0)),
+    (Color::Blue, (0, 0, 238)),
+    (Color::Magenta, (205, 0, 205)),
+    (Color::Cyan, (0, 205, 205)),
+    (Color::Gray, (229, 229, 229)),
+    (Color::DarkGray, (127, 127, 127)),
+    (Color::LightRed, (255, 0, 0)),
+    (Color::LightGreen, (0, 255, 0)),
+    (Color::LightYellow, (255, 255, 0)),
+    (Color::LightBlue, (92, 92, 255)),
+    (Color::LightMagenta, (255, 0, 255)),
+    (Color::LightCyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+// The channel values of xterm's 6x6x6 color cube.
+pub const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorDepth {
+    // The nearest color this depth can show. Only RGB colors need it; named and
+    // indexed ones pass through.
+    pub fn quantize(self, color: Color) -> Color {
+        let Color::Rgb(r, g, b) = color else {
+            return color;
+        };
+        match self {
+            ColorDepth::TrueColor => color,
+            ColorDepth::Ansi256 => Color::Indexed(nearest_256((r, g, b))),
+            // A mono terminal ignores color anyway, so it may as well get the
+            // plainest kind.
+            ColorDepth::Ansi16 | ColorDepth::Mono => nearest_16((r, g, b)),
+        }
+    }
+}
+
+pub fn distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let d = |x: u8, y: u8| (i32::from(x) - i32::from(y)).unsigned_abs().pow(2);
+    d(a.0, b.0) + d(a.1, b.1) + d(a.2, b.2)
+}
+
+fn nearest_16(rgb: (u8, u8, u8)) -> Color {
+    ANSI16
+        .iter()
+        .min_by_key(|(_, palette)| distance(rgb, *palette))
+        .map_or(Color::Reset, |(color, _)| *color)
+}
+
+// Which of CUBE_LEVELS is nearest `channel`.
+pub fn nearest_cube_level(channel: u8) -> usize {
+    (0..CUBE_LEVELS.len())
+        .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(channel))
+        .unwrap_or(0)
+}
+
+// The closer of the nearest cube color and the nearest of the 24 grays.
+fn nearest_256(rgb: (u8, u8, u8)) -> u8 {
+    let level = nearest_cube_level;
+    let (r, g, b) = (level(rgb.0), level(rgb.1), level(rgb.2));
+    let cube = (CUBE_LEVELS[r], CUBE_LEVELS[g], CUBE_LEVELS[b]);
+    let cube_index = 16 + 36 * r + 6 * g + b;
+
+    let average = (u32::from(rgb.0) + u32::from(rgb.1) + u32::from(rgb.2)) / 3;
+    let step = (average.saturating_sub(3) / 10).min(23);
+    let gray_level = (8 + step * 10) as u8;
+    let gray = (gray_level, gray_level, gray_level);
+
+    if distance(rgb, gray) < distance(rgb, cube) {
+        232 + step as u8
+    } else {
+        cube_index as u8
+    }
+}
+
+impl fmt::Display for ColorDepth {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ColorDepth::Mono => "mono",
+            ColorDepth::Ansi16 => "16",
+            ColorDepth::Ansi256 => "256",
+            ColorDepth::TrueColor => "24-bit",
+        };
+        f.write_str(name)
+    }
+}
+
+// What robert's pixels are drawn with, from finest to plainest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Glyphs {
+    Braille,
+    HalfBlock,
+    Block,
+    // `#`, `'` and `.`, for terminals that can't be trusted with anything but ASCII.
+    Ascii,
+}
+
+impl fmt::Display for Glyphs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Glyphs::Braille => "braille",
+            Glyphs::HalfBlock => "half blocks",
+            Glyphs::Block => "blocks",
+            Glyphs::Ascii => "ascii",
+        };
+        f.write_str(name)
+    }
+}
+
+// Ways of sending robert as an actual image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageProtocol {
+    Sixel,
+    Kitty,
+}
+
+impl fmt::Display for ImageProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ImageProtocol::Sixel => "sixel",
+            ImageProtocol::Kitty => "kitty",
+        };
+        f.write_str(name)
+    }
+}
+
+// What we know about a session's terminal, from the pty request and any TERM or
+// COLORTERM the client sends along.
+#[derive(Clone, Debug, Default)]
+pub struct ClientCaps {
+    pub term: Option<String>,
+    pub colorterm: Option<String>,
+    // Locale variables, in the order they override each other.
+    pub lc_all: Option<String>,
+    pub lc_ctype: Option<String>,
+    pub lang: Option<String>,
+    // Set from the client's answers to TERMINAL_QUERIES.
+    pub sixel: bool,
+    pub kitty: bool,
+    // The start of a reply whose end hasn't arrived yet.
+    partial_reply: Vec<u8>,
+}
+
+// Asks whether the terminal takes kitty graphics, then what it is. Everything answers
+// the second, so a terminal that ignores the first still says something. The answers
+// come back as input and are read by `read_replies`.
+pub const TERMINAL_QUERIES: &[u8] = b"\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[c";
+
+// Terminals that draw sixels or kitty graphics but might not say so.
+const SIXEL_TERMS: [&str; 4] = ["mlterm", "yaft-256color", "foot", "contour"];
+const KITTY_TERMS: [&str; 2] = ["xterm-kitty", "xterm-ghostty"];
+
+// Longer than any reply to TERMINAL_QUERIES, so an unfinished one this long never will be.
+const MAX_PARTIAL_REPLY: usize = 64;
+
+impl ClientCaps {
+    // Takes in the terminal's answers to TERMINAL_QUERIES. False if `data` is anything
+    // else, which is then input like any other. A reply cut off by the end of `data` is
+    // held on to until the rest of it arrives, rather than being taken for keystrokes.
+    pub fn read_replies(&mut self, data: &[u8]) -> bool {
+        if data.is_empty() {
+            return false;
+        }
+        // Whatever was held on to is dropped if `data` turns out not to finish it.
+        let data = [std::mem::take(&mut self.partial_reply).as_slice(), data].concat();
+        let mut rest = data.as_slice();
+        let (mut sixel, mut kitty) = (self.sixel, self.kitty);
+        while !rest.is_empty() {
+            if let Some(reply) = rest.strip_prefix(b"\x1b[?") {
+                // Primary device attributes, `ESC [ ? 64 ; 4 ; ... c`; 4 is sixel.
+                let end = reply
+                    .iter()
+                    .position(|byte| !byte.is_ascii_digit() && *byte != b';');
+                let Some(end) = end else {
+                    self.hold_partial_reply(rest);
+                    break;
+                };
+                if reply[end] != b'c' {
+                    return false;
+                }
+                sixel = reply[..end]
+                    .split(|&byte| byte == b';')
+                    .any(|attribute| attribute == b"4");
+                rest = &reply[end + 1..];
+            } else if let Some(reply) = rest.strip_prefix(b"\x1b_G") {
+                // `ESC _ G i=31 ; OK ESC \`, or an error in place of the OK.
+                let Some(end) = reply.windows(2).position(|pair| pair == b"\x1b\\") else {
+                    self.hold_partial_reply(rest);
+                    break;
+                };
+                kitty = reply[..end].ends_with(b";OK");
+                rest = &reply[end + 2..];
+            } else {
+                return false;
+            }
+        }
+        self.sixel = sixel;
+        self.kitty = kitty;
+        true
+    }
+
+    // Keeps the start of a reply for the next read, unless it's gone on too long to be
+    // one, in which case it's thrown away.
+    fn hold_partial_reply(&mut self, reply: &[u8]) {
+        if reply.len() < MAX_PARTIAL_REPLY {
+            self.partial_reply = reply.to_vec();
+        }
+    }
+
+    // Records an environment variable the client sent. False if it isn't one we use.
+    pub fn set_env(&mut self, name: &str, value: &str) -> bool {
+        match name {
+            "TERM" => self.term = Some(value.to_string()),
+            "COLORTERM" => self.colorterm = Some(value.to_string()),
+            "LC_ALL" => self.lc_all = Some(value.to_string()),
+            "LC_CTYPE" => self.lc_ctype = Some(value.to_string()),
+            "LANG" => self.lang = Some(value.to_string()),
+            _ => return false,
+        }
+        true
+    }
+
+    // Without a TERM to go on, assume a modern terminal, which is what robert was
+    // drawn for.
+    pub fn color_depth(&self) -> ColorDepth {
+        if matches!(self.colorterm.as_deref(), Some("truecolor" | "24bit")) {
+            return ColorDepth::TrueColor;
+        }
+
+        let Some(term) = self.term.as_deref() else {
+            return ColorDepth::TrueColor;
+        };
+        if term == "dumb" {
+            ColorDepth::Mono
+        } else if term.ends_with("-direct")
+            || term.ends_with("-truecolor")
+            || ["xterm-kitty", "alacritty", "wezterm", "foot"].contains(&term)
+        {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+
+    // Half blocks unless the terminal or locale says otherwise. Braille is never
+    // picked on its own, since it halves robert; `best_glyphs` allows it on request.
+    pub fn glyphs(&self) -> Glyphs {
+        let locale = self
+            .lc_all
+            .as_deref()
+            .or(self.lc_ctype.as_deref())
+            .or(self.lang.as_deref());
+        // An unset or empty locale tells us nothing, so it's taken as UTF-8.
+        if let Some(locale) = locale.filter(|locale| !locale.is_empty()) {
+            let locale = locale.to_ascii_lowercase();
+            if !locale.contains("utf-8") && !locale.contains("utf8") {
+                return Glyphs::Ascii;
+            }
+        }
+
+        match self.term.as_deref() {
+            Some(term) if ["dumb", "ansi", "vt52"].contains(&term) => Glyphs::Ascii,
+            Some(term) if term.starts_with("vt1") || term.starts_with("vt2") => Glyphs::Ascii,
+            // The console font has the block characters but no Braille, and its
+            // 16 colors make two pixels to a cell muddy.
+            Some("linux" | "cons25") => Glyphs::Block,
+            _ => Glyphs::HalfBlock,
+        }
+    }
+
+    // Kitty graphics where there's a choice, since they don't have to be resent to move.
+    pub fn image_protocol(&self) -> Option<ImageProtocol> {
+        let term = self.term.as_deref().unwrap_or_default();
+        if self.kitty || KITTY_TERMS.contains(&term) {
+            Some(ImageProtocol::Kitty)
+        } else if self.sixel || SIXEL_TERMS.contains(&term) || term.contains("sixel") {
+            Some(ImageProtocol::Sixel)
+        } else {
+            None
+        }
+    }
+
+    // Braille where half blocks would do, if it was asked for.
+    pub fn best_glyphs(&self, braille: bool) -> Glyphs {
+        match self.glyphs() {
+            Glyphs::HalfBlock if braille => Glyphs::Braille,
+            glyphs => glyphs,
+        }
+    }
+}
+
+impl fmt::Display for ClientCaps {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({}, {}",
+            self.term.as_deref().unwrap_or("-"),
+            self.color_depth(),
+            self.glyphs()
+        )?;
+        if let Some(protocol) = self.image_protocol() {
+            write!(f, ", {protocol}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps(env: &[(&str, &str)]) -> ClientCaps {
+        let mut caps = ClientCaps::default();
+        for (name, value) in env {
+            assert!(caps.set_env(name, value));
+        }
+        caps
+    }
+
+    #[test]
+    fn keeps_only_the_variables_it_uses() {
+        let mut caps = ClientCaps::default();
+        assert!(caps.set_env("TERM", "xterm-256color"));
+        assert!(caps.set_env("COLORTERM", "truecolor"));
+        assert!(!caps.set_env("EDITOR", "vi"));
+        assert_eq!(caps.term.as_deref(), Some("xterm-256color"));
+        assert_eq!(caps.colorterm.as_deref(), Some("truecolor"));
+    }
+
+    #[test]
+    fn picks_a_color_depth() {
+        for (env, depth) in [
+            (&[][..], ColorDepth::TrueColor),
+            (&[("TERM", "dumb")], ColorDepth::Mono),
+            (&[("TERM", "vt100")], ColorDepth::Ansi16),
+            (&[("TERM", "xterm")], ColorDepth::Ansi16),
+            (&[("TERM", "screen-256color")], ColorDepth::Ansi256),
+            (&[("TERM", "xterm-direct")], ColorDepth::TrueColor),
+            (&[("TERM", "alacritty")], ColorDepth::TrueColor),
+            (
+                &[("TERM", "xterm"), ("COLORTERM", "24bit")],
+                ColorDepth::TrueColor,
+            ),
+            (
+                &[("TERM", "xterm"), ("COLORTERM", "yes")],
+                ColorDepth::Ansi16,
+            ),
+        ] {
+            assert_eq!(caps(env).color_depth(), depth, "{env:?}");
+        }
+    }
+
+    #[test]
+    fn picks_glyphs() {
+        for (env, glyphs) in [
+            (&[][..], Glyphs::HalfBlock),
+            (
+                &[("TERM", "xterm-256color"), ("LANG", "en_GB.UTF-8")],
+                Glyphs::HalfBlock,
+            ),
+            (&[("TERM", "linux")], Glyphs::Block),
+            (&[("TERM", "vt220")], Glyphs::Ascii),
+            (&[("TERM", "dumb")], Glyphs::Ascii),
+            (&[("LANG", "C")], Glyphs::Ascii),
+            (&[("LANG", "")], Glyphs::HalfBlock),
+            // The most specific locale variable wins.
+            (
+                &[("LANG", "C"), ("LC_CTYPE", "en_US.utf8")],
+                Glyphs::HalfBlock,
+            ),
+            (
+                &[("LC_CTYPE", "en_US.utf8"), ("LC_ALL", "POSIX")],
+                Glyphs::Ascii,
+            ),
+        ] {
+            assert_eq!(caps(env).glyphs(), glyphs, "{env:?}");
+        }
+    }
+
+    #[test]
+    fn uses_braille_only_in_place_of_half_blocks() {
+        assert_eq!(caps(&[]).best_glyphs(true), Glyphs::Braille);
+        assert_eq!(caps(&[]).best_glyphs(false), Glyphs::HalfBlock);
+        assert_eq!(caps(&[("TERM", "linux")]).best_glyphs(true), Glyphs::Block);
+        assert_eq!(caps(&[("LANG", "C")]).best_glyphs(true), Glyphs::Ascii);
+    }
+
+    #[test]
+    fn quantizes_to_the_nearest_color() {
+        let rgb = |r, g, b| Color::Rgb(r, g, b);
+        assert_eq!(ColorDepth::TrueColor.quantize(rgb(1, 2, 3)), rgb(1, 2, 3));
+
+        assert_eq!(
+            ColorDepth::Ansi256.quantize(rgb(0, 0, 0)),
+            Color::Indexed(16)
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.quantize(rgb(255, 0, 0)),
+            Color::Indexed(196)
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.quantize(rgb(255, 255, 255)),
+            Color::Indexed(231)
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.quantize(rgb(100, 140, 170)),
+            Color::Indexed(67)
+        );
+        // Grays nearer the gray ramp than the cube.
+        assert_eq!(
+            ColorDepth::Ansi256.quantize(rgb(128, 128, 128)),
+            Color::Indexed(244)
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.quantize(rgb(240, 240, 240)),
+            Color::Indexed(255)
+        );
+
+        assert_eq!(ColorDepth::Ansi16.quantize(rgb(250, 5, 5)), Color::LightRed);
+        assert_eq!(ColorDepth::Ansi16.quantize(rgb(190, 10, 0)), Color::Red);
+        assert_eq!(ColorDepth::Mono.quantize(rgb(20, 20, 20)), Color::Black);
+    }
+
+    #[test]
+    fn leaves_named_and_indexed_colors_alone() {
+        for depth in [ColorDepth::Mono, ColorDepth::Ansi16, ColorDepth::Ansi256] {
+            assert_eq!(depth.quantize(Color::Cyan), Color::Cyan);
+            assert_eq!(depth.quantize(Color::Indexed(200)), Color::Indexed(200));
+        }
+    }
+
+    const DA1_SIXEL: &[u8] = b"\x1b[?64;1;4;22c";
+    const KITTY_OK: &[u8] = b"\x1b_Gi=31;OK\x1b\\";
+
+    #[test]
+    fn reads_replies_to_the_queries() {
+        let mut caps = ClientCaps::default();
+        assert!(caps.read_replies(&[KITTY_OK, DA1_SIXEL].concat()));
+        assert!(caps.sixel && caps.kitty);
+
+        let mut caps = ClientCaps::default();
+        assert!(caps.read_replies(b"\x1b_Gi=31;EINVAL:unsupported\x1b\\\x1b[?62;22c"));
+        assert!(!caps.sixel && !caps.kitty);
+    }
+
+    #[test]
+    fn leaves_anything_else_as_input() {
+        let mut caps = ClientCaps::default();
+        for input in [&b""[..], b"q", b"\x1b[A", b"\x1b[?64;4x", b"\x1b[?64;4cq"] {
+            assert!(!caps.read_replies(input), "{input:?} was taken as a reply");
+        }
+        assert!(!caps.sixel);
+    }
+
+    #[test]
+    fn waits_for_the_rest_of_a_split_reply() {
+        for reply in [DA1_SIXEL, KITTY_OK] {
+            for split in 3..reply.len() {
+                let mut caps = ClientCaps::default();
+                assert!(caps.read_replies(&reply[..split]));
+                assert!(caps.read_replies(&reply[split..]), "split at {split}");
+                assert!(caps.sixel || caps.kitty, "split at {split}");
+                assert!(!caps.read_replies(b"q"));
+            }
+        }
+
+        let mut caps = ClientCaps::default();
+        assert!(caps.read_replies(&[KITTY_OK, &DA1_SIXEL[..6]].concat()));
+        assert!(caps.kitty && !caps.sixel);
+        assert!(caps.read_replies(&DA1_SIXEL[6..]));
+        assert!(caps.sixel);
+    }
+
+    #[test]
+    fn drops_a_split_reply_that_never_finishes() {
+        let mut caps = ClientCaps::default();
+        assert!(caps.read_replies(b"\x1b[?64;"));
+        assert!(!caps.read_replies(b"q"));
+        assert!(!caps.read_replies(b"4c"));
+        assert!(!caps.sixel);
+
+        let mut caps = ClientCaps::default();
+        assert!(caps.read_replies(b"\x1b_Gi=31;"));
+        assert!(caps.read_replies(&[b'x'; MAX_PARTIAL_REPLY]));
+        assert!(!caps.read_replies(b"OK\x1b\\"));
+        assert!(!caps.kitty);
+    }
+}
@@ -0,0 +1,92 @@
+use std::time::{Duration, Instant};
+
+use rand::{Rng, rngs::StdRng};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+};
+
+use crate::particles::{Particle, Particles};
+
+// How long the confetti flies after a corner hit.
+const DURATION: Duration = Duration::from_millis(1500);
+// The screen flashes for the start of it.
+const FLASH: Duration = Duration::from_millis(120);
+const PIECES: usize = 48;
+// Added to each piece's downward speed every frame at 30 FPS, in rows.
+const GRAVITY: f64 = 0.04;
+const COLORS: [Color; 6] = [
+    Color::Rgb(255, 80, 80),
+    Color::Rgb(255, 200, 60),
+    Color::Rgb(120, 230, 90),
+    Color::Rgb(80, 180, 255),
+    Color::Rgb(200, 110, 255),
+    Color::Rgb(255, 255, 255),
+];
+// Plain ASCII, so it works whatever glyphs the terminal has.
+const SYMBOLS: [char; 4] = ['*', '+', 'o', '.'];
+
+// Confetti thrown out of the corner robert just hit, after a quick flash.
+pub struct Celebration {
+    pieces: Particles,
+    started_at: Instant,
+    hits: u32,
+}
+
+impl Celebration {
+    // `corner` is the corner of the window robert hit, in cells, and `hits` how many
+    // corners that makes.
+    pub fn new(rng: &mut StdRng, corner: (f64, f64), hits: u32) -> Self {
+        // Out of the corner and into the window.
+        let away_x = if corner.0 > 0.0 { -1.0 } else { 1.0 };
+        let away_y = if corner.1 > 0.0 { -1.0 } else { 1.0 };
+        let mut pieces = Particles::default();
+        for _ in 0..PIECES {
+            pieces.spawn(Particle {
+                position: corner,
+                velocity: (
+                    away_x * rng.gen_range(0.3..2.5),
+                    away_y * rng.gen_range(0.1..1.2),
+                ),
+                gravity: GRAVITY,
+                lifetime: DURATION,
+                color: COLORS[rng.gen_range(0..COLORS.len())],
+                symbol: SYMBOLS[rng.gen_range(0..SYMBOLS.len())],
+            });
+        }
+        Self {
+            pieces,
+            started_at: Instant::now(),
+            hits,
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.started_at.elapsed() >= DURATION
+    }
+
+    // `scale` is how far to move the pieces, relative to one frame at 30 FPS.
+    pub fn update(&mut self, scale: f64) {
+        self.pieces.update(scale);
+    }
+
+    // Drawn inside `area`, which the corner was given relative to.
+    pub fn draw(&self, frame: &mut Frame, area: Rect) {
+        let buffer = frame.buffer_mut();
+        if self.started_at.elapsed() < FLASH {
+            buffer.set_style(area, Style::default().add_modifier(Modifier::REVERSED));
+        }
+
+        self.pieces.draw(buffer, area);
+
+        let label = format!(" corner #{}! ", self.hits);
+        let x = area.x + area.width.saturating_sub(label.len() as u16) / 2;
+        buffer.set_string(
+            x,
+            area.y + area.height / 2,
+            label,
+            Style::default().fg(Color::Black).bg(Color::Yellow),
+        );
+    }
+}
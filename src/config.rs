@@ -0,0 +1,543 @@
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::NaiveTime;
+use chrono::format::StrftimeItems;
+use image::Rgb;
+use russh::keys::Algorithm;
+use russh::{Preferred, cipher, compression, kex, mac};
+use serde::{Deserialize, Deserializer};
+use socket2::{SockRef, TcpKeepalive};
+use tokio::net::TcpStream;
+
+// Settings read from the `--config` TOML file. Every section and key is optional.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigFile {
+    pub theme: Theme,
+    // Sessions start with robert at a calm, steady speed. See `App::reduced_motion`.
+    pub reduced_motion: bool,
+    // Rings the terminal bell every time robert bounces.
+    pub bell: bool,
+    // One of app::SEASONS by name, or "none". Left out, the month picks.
+    #[serde(deserialize_with = "season")]
+    pub season: Option<String>,
+    pub algorithms: AlgorithmConfig,
+    pub connection: ConnectionConfig,
+    pub background: Option<Background>,
+    pub status_bar: StatusBarConfig,
+    pub clock: Option<ClockConfig>,
+    pub day_night: Option<DayNightConfig>,
+    pub marquee: Option<MarqueeConfig>,
+    pub quips: Option<QuipsConfig>,
+    pub physics: PhysicsConfig,
+    pub scared: ScaredConfig,
+}
+
+impl ConfigFile {
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let contents = std::fs::read_to_string(path)?;
+        Ok(toml::from_str(&contents)?)
+    }
+}
+
+// How sessions start out looking. Being plain keys, these go above any section.
+//
+//     theme = "high-contrast"   # robert in a few bright colors on black
+//     reduced_motion = true
+//     bell = true               # off by default, for everyone's sake
+//     season = "snow"           # or "hearts", or "none"; the month picks otherwise
+#[derive(Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    #[default]
+    Normal,
+    HighContrast,
+}
+
+impl Theme {
+    // Spelt as in the config file, which is also how profiles save it.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "normal" => Some(Theme::Normal),
+            "high-contrast" => Some(Theme::HighContrast),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Normal => "normal",
+            Theme::HighContrast => "high-contrast",
+        }
+    }
+}
+
+// Algorithm names as OpenSSH spells them, most preferred first. A list that isn't set
+// keeps russh's defaults.
+//
+//     [algorithms]
+//     kex = ["curve25519-sha256", "curve25519-sha256@libssh.org"]
+//     cipher = ["chacha20-poly1305@openssh.com", "aes256-gcm@openssh.com"]
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct AlgorithmConfig {
+    pub kex: Option<Vec<String>>,
+    pub host_key: Option<Vec<String>>,
+    pub cipher: Option<Vec<String>>,
+    pub mac: Option<Vec<String>>,
+    pub compression: Option<Vec<String>>,
+}
+
+impl AlgorithmConfig {
+    pub fn preferred(&self) -> Result<Preferred, anyhow::Error> {
+        let mut preferred = Preferred::default();
+
+        if let Some(names) = &self.kex {
+            let mut kex = parse_names(names, "kex", |name| kex::Name::try_from(name).ok())?;
+            // russh negotiates ext-info and strict kex through pseudo-algorithms in this
+            // list, so they stay in whatever the operator picked.
+            for extension in [
+                kex::EXTENSION_SUPPORT_AS_SERVER,
+                kex::EXTENSION_OPENSSH_STRICT_KEX_AS_SERVER,
+            ] {
+                if !kex.contains(&extension) {
+                    kex.push(extension);
+                }
+            }
+            preferred.kex = kex.into();
+        }
+        if let Some(names) = &self.host_key {
+            preferred.key = parse_names(names, "host key", |name| {
+                Algorithm::new(name)
+                    .ok()
+                    .filter(|algorithm| !matches!(algorithm, Algorithm::Other(_)))
+            })?
+            .into();
+        }
+        if let Some(names) = &self.cipher {
+            preferred.cipher =
+                parse_names(names, "cipher", |name| cipher::Name::try_from(name).ok())?.into();
+        }
+        if let Some(names) = &self.mac {
+            preferred.mac =
+                parse_names(names, "mac", |name| mac::Name::try_from(name).ok())?.into();
+        }
+        if let Some(names) = &self.compression {
+            preferred.compression = parse_names(names, "compression", |name| {
+                compression::Name::try_from(name).ok()
+            })?
+            .into();
+        }
+
+        Ok(preferred)
+    }
+}
+
+fn parse_names<T>(
+    names: &[String],
+    kind: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>, anyhow::Error> {
+    if names.is_empty() {
+        return Err(anyhow::anyhow!("The {kind} algorithm list is empty"));
+    }
+    names
+        .iter()
+        .map(|name| parse(name).ok_or_else(|| anyhow::anyhow!("Unknown {kind} algorithm: {name}")))
+        .collect()
+}
+
+// Timeouts and socket options, shown here with their defaults. The socket options apply
+// to every listener, not just SSH.
+//
+//     [connection]
+//     inactivity_timeout_secs = 3600  # 0 never times out
+//     auth_rejection_secs = 3
+//     tcp_keepalive_secs = 60         # unset leaves TCP keepalive off
+//     nodelay = true
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConnectionConfig {
+    pub inactivity_timeout_secs: u64,
+    pub auth_rejection_secs: u64,
+    pub tcp_keepalive_secs: Option<u64>,
+    pub nodelay: bool,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> Self {
+        Self {
+            inactivity_timeout_secs: 3600,
+            auth_rejection_secs: 3,
+            tcp_keepalive_secs: None,
+            nodelay: true,
+        }
+    }
+}
+
+impl ConnectionConfig {
+    pub fn inactivity_timeout(&self) -> Option<Duration> {
+        (self.inactivity_timeout_secs > 0)
+            .then(|| Duration::from_secs(self.inactivity_timeout_secs))
+    }
+
+    pub fn auth_rejection_time(&self) -> Duration {
+        Duration::from_secs(self.auth_rejection_secs)
+    }
+
+    // Sets the socket options on a connection that was just accepted.
+    pub fn apply(&self, stream: &TcpStream) -> std::io::Result<()> {
+        stream.set_nodelay(self.nodelay)?;
+        if let Some(secs) = self.tcp_keepalive_secs {
+            let keepalive = TcpKeepalive::new().with_time(Duration::from_secs(secs));
+            SockRef::from(stream).set_tcp_keepalive(&keepalive)?;
+        }
+        Ok(())
+    }
+}
+
+// A line along the bottom of the bouncing screen with the session count, the server's
+// uptime and the keys. Off unless turned on.
+//
+//     [status_bar]
+//     enabled = true
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatusBarConfig {
+    pub enabled: bool,
+}
+
+// A clock in a corner of the bouncing screen, in the server's local time. The format
+// takes strftime fields, as chrono spells them.
+//
+//     [clock]
+//     position = "top-right"   # or top-left, bottom-left, bottom-right
+//     format = "%H:%M:%S"
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClockConfig {
+    pub position: Corner,
+    #[serde(deserialize_with = "time_format")]
+    pub format: String,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        Self {
+            position: Corner::TopRight,
+            format: "%H:%M:%S".to_string(),
+        }
+    }
+}
+
+// Tints robert and the background by the server's local time: warm from `dusk` until
+// `night`, dark blue from then until `dawn`, and left alone through the day. Times are
+// 24-hour HH:MM.
+//
+//     [day_night]
+//     dawn = "06:30"   # the defaults
+//     dusk = "18:30"
+//     night = "20:30"
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct DayNightConfig {
+    #[serde(deserialize_with = "time_of_day")]
+    pub dawn: NaiveTime,
+    #[serde(deserialize_with = "time_of_day")]
+    pub dusk: NaiveTime,
+    #[serde(deserialize_with = "time_of_day")]
+    pub night: NaiveTime,
+}
+
+impl Default for DayNightConfig {
+    fn default() -> Self {
+        let at = |hour, minute| NaiveTime::from_hms_opt(hour, minute, 0).unwrap_or_default();
+        Self {
+            dawn: at(6, 30),
+            dusk: at(18, 30),
+            night: at(20, 30),
+        }
+    }
+}
+
+impl DayNightConfig {
+    // The color everything's tinted toward at `now`, and how far, or None by day.
+    pub fn tint(&self, now: NaiveTime) -> Option<(Rgb<u8>, f64)> {
+        // Ranges can run past midnight.
+        let between = |start: NaiveTime, end: NaiveTime| {
+            if start <= end {
+                start <= now && now < end
+            } else {
+                now >= start || now < end
+            }
+        };
+        if between(self.dusk, self.night) {
+            Some((Rgb([255, 140, 60]), 0.25))
+        } else if between(self.night, self.dawn) {
+            Some((Rgb([10, 20, 70]), 0.5))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+}
+
+// Text scrolling right to left along the top or bottom of the bouncing screen, for
+// announcements or credits.
+//
+//     [marquee]
+//     text = "robert says hi"
+//     position = "bottom"   # or top, the default
+//     speed = 10.0          # cells a second
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct MarqueeConfig {
+    pub text: String,
+    pub position: Edge,
+    #[serde(deserialize_with = "scroll_speed")]
+    pub speed: f64,
+}
+
+impl Default for MarqueeConfig {
+    fn default() -> Self {
+        Self {
+            text: String::new(),
+            position: Edge::Top,
+            speed: 10.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum Edge {
+    Top,
+    Bottom,
+}
+
+// Every so often robert says something from `messages` in a speech bubble. Leaving
+// `messages` out keeps his own lines.
+//
+//     [quips]
+//     messages = ["hi!", "is this thing on?"]
+//     every_secs = 20.0   # on average
+//     show_secs = 4.0
+#[derive(Deserialize, Clone, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuipsConfig {
+    pub messages: Vec<String>,
+    #[serde(deserialize_with = "seconds")]
+    pub every_secs: f64,
+    #[serde(deserialize_with = "seconds")]
+    pub show_secs: f64,
+}
+
+impl Default for QuipsConfig {
+    fn default() -> Self {
+        Self {
+            messages: ["boing", "wheee!", "am I there yet?", "so many walls", "hi!"]
+                .map(String::from)
+                .to_vec(),
+            every_secs: 20.0,
+            show_secs: 4.0,
+        }
+    }
+}
+
+// How robert moves, for giving him a different personality. He cruises at `cruise_x`
+// columns and `cruise_y` rows a frame at 30 FPS. Each wall has odds of sending him off
+// in a burst of `burst_x` or `burst_y` instead, and `drag` is the share of his speed
+// above cruising that he loses each second, from 0, where a burst lasts until the next
+// wall, up to 1.
+//
+//     [physics]
+//     drag = 0.75          # these are the defaults
+//     cruise_x = 1.5
+//     cruise_y = 1.0
+//     burst_odds_x = 0.5   # off the left and right walls
+//     burst_odds_y = 0.2   # off the top and bottom
+//     burst_x = 20.0
+//     burst_y = 5.0
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhysicsConfig {
+    #[serde(deserialize_with = "fraction")]
+    pub drag: f64,
+    #[serde(deserialize_with = "speed")]
+    pub cruise_x: f64,
+    #[serde(deserialize_with = "speed")]
+    pub cruise_y: f64,
+    #[serde(deserialize_with = "fraction")]
+    pub burst_odds_x: f64,
+    #[serde(deserialize_with = "fraction")]
+    pub burst_odds_y: f64,
+    #[serde(deserialize_with = "speed")]
+    pub burst_x: f64,
+    #[serde(deserialize_with = "speed")]
+    pub burst_y: f64,
+}
+
+impl Default for PhysicsConfig {
+    fn default() -> Self {
+        Self {
+            drag: 0.75,
+            cruise_x: 1.5,
+            cruise_y: 1.0,
+            burst_odds_x: 0.5,
+            burst_odds_y: 0.2,
+            burst_x: 20.0,
+            burst_y: 5.0,
+        }
+    }
+}
+
+// How long a fright keeps robert scared, whatever his speed, and how long the scared
+// sprite then takes to blend back into his usual one.
+//
+//     [scared]
+//     secs = 2.0         # the default
+//     blend_secs = 0.5
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct ScaredConfig {
+    #[serde(deserialize_with = "seconds")]
+    pub secs: f64,
+    #[serde(deserialize_with = "seconds")]
+    pub blend_secs: f64,
+}
+
+impl Default for ScaredConfig {
+    fn default() -> Self {
+        Self {
+            secs: 2.0,
+            blend_secs: 0.5,
+        }
+    }
+}
+
+// What fills the window behind robert. Colors are RRGGBB, as with --chroma-key.
+//
+//     [background]
+//     style = "solid"
+//     color = "101020"
+//
+//     [background]
+//     style = "gradient"
+//     top = "000030"
+//     bottom = "301040"
+//
+//     [background]
+//     style = "starfield"
+//     sky = "000000"   # the default
+//     density = 0.02   # share of cells with a star, the default
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(tag = "style", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Background {
+    Solid {
+        #[serde(deserialize_with = "color")]
+        color: Rgb<u8>,
+    },
+    Gradient {
+        #[serde(deserialize_with = "color")]
+        top: Rgb<u8>,
+        #[serde(deserialize_with = "color")]
+        bottom: Rgb<u8>,
+    },
+    Starfield {
+        #[serde(deserialize_with = "color", default = "black")]
+        sky: Rgb<u8>,
+        #[serde(deserialize_with = "fraction", default = "star_density")]
+        density: f64,
+    },
+}
+
+fn color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rgb<u8>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    crate::parse_color(&s).map_err(serde::de::Error::custom)
+}
+
+// Checked here, since chrono only finds a bad field when it comes to print it.
+fn time_format<'de, D: Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    StrftimeItems::new(&s)
+        .parse()
+        .map_err(|_| serde::de::Error::custom(format!("{s} isn't a valid time format")))?;
+    Ok(s)
+}
+
+fn fraction<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if !(0.0..=1.0).contains(&value) {
+        return Err(serde::de::Error::custom(format!(
+            "{value} isn't between 0 and 1"
+        )));
+    }
+    Ok(value)
+}
+
+// In cells a frame at 30 FPS, up to what a window can make any sense of.
+fn speed<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    const MAX_SPEED: f64 = 100.0;
+    let value = f64::deserialize(deserializer)?;
+    if !(0.0..=MAX_SPEED).contains(&value) {
+        return Err(serde::de::Error::custom(format!(
+            "{value} isn't a speed between 0 and {MAX_SPEED}"
+        )));
+    }
+    Ok(value)
+}
+
+// In cells a second, which has to get the marquee somewhere without outrunning the
+// window many times over.
+fn scroll_speed<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    const MAX_SCROLL_SPEED: f64 = 1000.0;
+    let value = f64::deserialize(deserializer)?;
+    if !(value > 0.0 && value <= MAX_SCROLL_SPEED) {
+        return Err(serde::de::Error::custom(format!(
+            "{value} isn't a scroll speed above 0 and up to {MAX_SCROLL_SPEED}"
+        )));
+    }
+    Ok(value)
+}
+
+fn season<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    if s != "none" && !crate::app::SEASONS.iter().any(|season| season.name == s) {
+        return Err(serde::de::Error::custom(format!("{s} isn't a season")));
+    }
+    Ok(Some(s))
+}
+
+fn time_of_day<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveTime, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    NaiveTime::parse_from_str(&s, "%H:%M")
+        .map_err(|_| serde::de::Error::custom(format!("{s} isn't a time like 18:30")))
+}
+
+// A length of time, which can't be negative or anywhere near too long for a Duration.
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let value = f64::deserialize(deserializer)?;
+    if !(0.0..=f64::from(u32::MAX)).contains(&value) {
+        return Err(serde::de::Error::custom(format!(
+            "{value} isn't a number of seconds"
+        )));
+    }
+    Ok(value)
+}
+
+fn black() -> Rgb<u8> {
+    Rgb([0, 0, 0])
+}
+
+fn star_density() -> f64 {
+    0.02
+}
@@ -0,0 +1,51 @@
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+// Corner hits across every session since the counter was started. With a path it's
+// kept in a file holding just the number, so it carries over restarts.
+pub struct CornerCounter {
+    path: Option<PathBuf>,
+    total: Mutex<u64>,
+}
+
+impl CornerCounter {
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            total: Mutex::new(0),
+        }
+    }
+
+    // A missing file starts the count at zero; it gets created on the first hit.
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let total = match std::fs::read_to_string(path) {
+            Ok(contents) => contents.trim().parse()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            total: Mutex::new(total),
+        })
+    }
+
+    pub fn total(&self) -> u64 {
+        *self.total.lock().unwrap()
+    }
+
+    // The file is written under the lock, so two sessions hitting at once can't leave
+    // the smaller number behind.
+    pub fn hit(&self) {
+        let mut total = self.total.lock().unwrap();
+        *total += 1;
+        if let Some(path) = &self.path {
+            // Written next to the real file and renamed over it, like profiles.
+            let temp_path = path.with_extension("tmp");
+            let saved = std::fs::write(&temp_path, format!("{total}\n"))
+                .and_then(|()| std::fs::rename(&temp_path, path));
+            if let Err(e) = saved {
+                eprintln!("Failed to save corner hits: {e}");
+            }
+        }
+    }
+}
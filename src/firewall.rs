@@ -0,0 +1,149 @@
+use std::net::IpAddr;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl Cidr {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u32::from(network) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - u32::from(self.prefix_len))
+                    .unwrap_or(0);
+                u128::from(network) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+// Accepts `10.0.0.0/8`, `2001:db8::/32`, or a bare address meaning a single host.
+impl FromStr for Cidr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match s.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len)),
+            None => (s, None),
+        };
+
+        let network = address
+            .parse::<IpAddr>()
+            .map_err(|e| format!("invalid address in {s}: {e}"))?
+            .to_canonical();
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+
+        let prefix_len = match prefix_len {
+            Some(prefix_len) => prefix_len
+                .parse::<u8>()
+                .map_err(|e| format!("invalid prefix length in {s}: {e}"))?,
+            None => max_len,
+        };
+        if prefix_len > max_len {
+            return Err(format!("prefix length in {s} is longer than {max_len}"));
+        }
+
+        Ok(Self {
+            network,
+            prefix_len,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Firewall {
+    allow: Vec<Cidr>,
+    deny: Vec<Cidr>,
+}
+
+impl Firewall {
+    pub fn new(allow: Vec<Cidr>, deny: Vec<Cidr>) -> Self {
+        Self { allow, deny }
+    }
+
+    // Deny rules win; an empty allowlist lets everyone else through.
+    pub fn allows(&self, ip: IpAddr) -> bool {
+        if self.deny.iter().any(|cidr| cidr.contains(ip)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|cidr| cidr.contains(ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_networks_and_bare_addresses() {
+        let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(cidr.contains(ip("10.200.3.4")));
+        assert!(!cidr.contains(ip("11.0.0.1")));
+
+        let host: Cidr = "192.168.1.5".parse().unwrap();
+        assert!(host.contains(ip("192.168.1.5")));
+        assert!(!host.contains(ip("192.168.1.6")));
+
+        let v6: Cidr = "2001:db8::/32".parse().unwrap();
+        assert!(v6.contains(ip("2001:db8:ffff::1")));
+        assert!(!v6.contains(ip("2001:db9::1")));
+    }
+
+    #[test]
+    fn zero_prefix_matches_everything_of_its_family() {
+        let any: Cidr = "0.0.0.0/0".parse().unwrap();
+        assert!(any.contains(ip("203.0.113.9")));
+        assert!(!any.contains(ip("::2")));
+    }
+
+    #[test]
+    fn mapped_ipv4_matches_ipv4_rules() {
+        let cidr: Cidr = "10.0.0.0/8".parse().unwrap();
+        assert!(cidr.contains(ip("::ffff:10.1.2.3")));
+        let mapped: Cidr = "::ffff:10.0.0.0/8".parse().unwrap();
+        assert!(mapped.contains(ip("10.1.2.3")));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        for bad in [
+            "",
+            "/",
+            "10.0.0.0/",
+            "10.0.0/8",
+            "10.0.0.0/33",
+            "2001:db8::/129",
+            "10.0.0.0/-1",
+            "10.0.0.0/8/8",
+            "not an address",
+        ] {
+            assert!(bad.parse::<Cidr>().is_err(), "{bad:?} parsed");
+        }
+    }
+
+    #[test]
+    fn deny_wins_over_allow() {
+        let firewall = Firewall::new(
+            vec!["10.0.0.0/8".parse().unwrap()],
+            vec!["10.0.0.1".parse().unwrap()],
+        );
+        assert!(firewall.allows(ip("10.0.0.2")));
+        assert!(!firewall.allows(ip("10.0.0.1")));
+        assert!(!firewall.allows(ip("192.0.2.1")));
+        assert!(Firewall::default().allows(ip("192.0.2.1")));
+    }
+}
@@ -0,0 +1,166 @@
+use image::imageops::{self, FilterType};
+use rand::{Rng, rngs::StdRng};
+use ratatui::{
+    Frame,
+    symbols::Marker,
+    widgets::canvas::{Canvas, Points},
+};
+
+use crate::app::{PixelMap, blocks_to_ascii, to_pixel_map};
+use crate::caps::Glyphs;
+
+const BOIDS: usize = 12;
+// Each one is robert shrunk to this many pixels square, which is twice as many columns
+// as rows.
+const BOID_PIXELS: u32 = 8;
+// How far a robert looks for the rest of the flock, and how close he lets them get, in
+// columns. Rows count double, since they're twice as tall.
+const SIGHT: f64 = 16.0;
+const PERSONAL_SPACE: f64 = 8.0;
+// How hard each rule steers, per frame at 30 FPS.
+const SEPARATION: f64 = 0.05;
+const ALIGNMENT: f64 = 0.05;
+const COHESION: f64 = 0.002;
+// Speed limits, in columns a frame at 30 FPS.
+const MIN_SPEED: f64 = 0.3;
+const MAX_SPEED: f64 = 1.0;
+
+struct Boid {
+    // The top left of his sprite, in cells.
+    position: (f64, f64),
+    velocity: (f64, f64),
+}
+
+// A dozen small roberts that keep apart, line up with their neighbours and drift
+// toward them, and so move as a flock. They wrap around the edges.
+#[derive(Default)]
+pub struct Flock {
+    boids: Vec<Boid>,
+    size: (u16, u16),
+    sprite: Option<PixelMap>,
+}
+
+impl Flock {
+    // `scale` is how far to move the flock, relative to one frame at 30 FPS.
+    pub fn update(&mut self, scale: f64) {
+        // Nowhere to wrap around to until there's a window.
+        if self.size.0 == 0 || self.size.1 == 0 {
+            return;
+        }
+        let (width, height) = (f64::from(self.size.0), f64::from(self.size.1));
+        let mut steering = Vec::with_capacity(self.boids.len());
+        for boid in &self.boids {
+            let mut separation = (0.0, 0.0);
+            let mut velocity = (0.0, 0.0);
+            let mut centre = (0.0, 0.0);
+            let mut neighbours = 0.0;
+            for other in &self.boids {
+                let dx = other.position.0 - boid.position.0;
+                let dy = (other.position.1 - boid.position.1) * 2.0;
+                let distance = dx.hypot(dy);
+                if distance == 0.0 || distance > SIGHT {
+                    continue;
+                }
+                if distance < PERSONAL_SPACE {
+                    separation.0 -= dx / distance;
+                    separation.1 -= dy / distance / 2.0;
+                }
+                velocity.0 += other.velocity.0;
+                velocity.1 += other.velocity.1;
+                centre.0 += other.position.0;
+                centre.1 += other.position.1;
+                neighbours += 1.0;
+            }
+
+            let mut steer = (separation.0 * SEPARATION, separation.1 * SEPARATION);
+            if neighbours > 0.0 {
+                steer.0 += (velocity.0 / neighbours - boid.velocity.0) * ALIGNMENT;
+                steer.1 += (velocity.1 / neighbours - boid.velocity.1) * ALIGNMENT;
+                steer.0 += (centre.0 / neighbours - boid.position.0) * COHESION;
+                steer.1 += (centre.1 / neighbours - boid.position.1) * COHESION;
+            }
+            steering.push(steer);
+        }
+
+        for (boid, steer) in self.boids.iter_mut().zip(steering) {
+            boid.velocity.0 += steer.0 * scale;
+            boid.velocity.1 += steer.1 * scale;
+            let speed = boid.velocity.0.hypot(boid.velocity.1 * 2.0);
+            if speed > 0.0 {
+                let limited = speed.clamp(MIN_SPEED, MAX_SPEED);
+                boid.velocity.0 *= limited / speed;
+                boid.velocity.1 *= limited / speed;
+            }
+            boid.position.0 = (boid.position.0 + boid.velocity.0 * scale).rem_euclid(width);
+            boid.position.1 = (boid.position.1 + boid.velocity.1 * scale).rem_euclid(height);
+        }
+    }
+
+    // The flock is let loose afresh whenever the window changes size. `robert` is the
+    // sprite each boid is a small copy of, drawn with the session's `glyphs` the way
+    // robert himself is.
+    pub fn draw(&mut self, frame: &mut Frame, rng: &mut StdRng, robert: &PixelMap, glyphs: Glyphs) {
+        let area = frame.area();
+        if self.size != (area.width, area.height) {
+            self.size = (area.width, area.height);
+            self.boids = (0..BOIDS)
+                .map(|_| Self::new_boid(rng, area.width, area.height))
+                .collect();
+        }
+        let sprite = self.sprite.get_or_insert_with(|| {
+            to_pixel_map(&imageops::resize(
+                &robert.image,
+                BOID_PIXELS,
+                BOID_PIXELS,
+                FilterType::Nearest,
+            ))
+        });
+
+        let (marker, pixel_scale) = match glyphs {
+            Glyphs::Braille => (Marker::Braille, 0.5),
+            Glyphs::HalfBlock | Glyphs::Ascii => (Marker::HalfBlock, 1.0),
+            Glyphs::Block => (Marker::Block, 1.0),
+        };
+        let (width, height) = (f64::from(area.width), f64::from(area.height));
+        let canvas = Canvas::default()
+            .marker(marker)
+            .x_bounds([0.0, width])
+            .y_bounds([0.0, height])
+            .paint(|ctx| {
+                let mut coords = Vec::new();
+                for (color, pixels) in &sprite.points {
+                    coords.clear();
+                    for boid in &self.boids {
+                        let (left, top) = boid.position;
+                        coords.extend(pixels.iter().map(|&(x, y)| {
+                            (left + x * pixel_scale, height - top - y * pixel_scale)
+                        }));
+                    }
+                    ctx.draw(&Points {
+                        coords: &coords,
+                        color: *color,
+                    });
+                }
+            });
+        frame.render_widget(canvas, area);
+        if glyphs == Glyphs::Ascii {
+            blocks_to_ascii(frame.buffer_mut(), area);
+        }
+    }
+
+    // The sprite is made again on the next draw, after robert's art changes.
+    pub fn forget_sprite(&mut self) {
+        self.sprite = None;
+    }
+
+    fn new_boid(rng: &mut StdRng, width: u16, height: u16) -> Boid {
+        let angle = rng.gen_range(0.0..std::f64::consts::TAU);
+        Boid {
+            position: (
+                rng.gen_range(0.0..f64::from(width.max(1))),
+                rng.gen_range(0.0..f64::from(height.max(1))),
+            ),
+            velocity: (angle.cos() * MAX_SPEED, angle.sin() * MAX_SPEED / 2.0),
+        }
+    }
+}
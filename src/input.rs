@@ -0,0 +1,14 @@
+// What a client outside SSH sends, once its protocol's framing is taken off.
+#[derive(Debug, PartialEq, Eq)]
+pub enum InputEvent {
+    Keys(Vec<u8>),
+    // A new window size, in columns and rows.
+    Resize(u16, u16),
+}
+
+// The incoming side of a telnet or browser connection.
+pub trait InputSource {
+    // Whatever arrived with the next read, which may be nothing useful. None once the
+    // client has gone. Must be safe to cancel, since reads are raced against a timeout.
+    async fn read(&mut self) -> Option<Vec<InputEvent>>;
+}
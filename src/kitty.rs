@@ -0,0 +1,38 @@
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+
+// Terminals take the payload in pieces of at most this many base64 bytes.
+const CHUNK_SIZE: usize = 4096;
+
+// Hands the terminal a PNG to keep as image `id`, replacing any it already has.
+// Nothing is shown until it's placed.
+pub fn transmit(id: u32, png: &[u8]) -> Vec<u8> {
+    let encoded = BASE64_STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(CHUNK_SIZE).collect();
+
+    let mut out = Vec::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        // The keys only go on the first piece. q=2 keeps the terminal from answering.
+        if i == 0 {
+            out.extend(format!("\x1b_Ga=t,f=100,i={id},q=2,m={more};").bytes());
+        } else {
+            out.extend(format!("\x1b_Gm={more};").bytes());
+        }
+        out.extend_from_slice(chunk);
+        out.extend_from_slice(b"\x1b\\");
+    }
+    out
+}
+
+// Shows image `id` at the cursor, stretched over `cols` x `rows` cells. Placing it
+// again moves it, since the placement id stays the same.
+pub fn place(id: u32, cols: u16, rows: u16) -> Vec<u8> {
+    // C=1 leaves the cursor where it was.
+    format!("\x1b_Ga=p,i={id},p=1,c={cols},r={rows},C=1,q=2\x1b\\").into_bytes()
+}
+
+// Takes image `id` off the screen, keeping it for the next `place`.
+pub fn hide(id: u32) -> Vec<u8> {
+    format!("\x1b_Ga=d,d=i,i={id},q=2\x1b\\").into_bytes()
+}
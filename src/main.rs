@@ -1,11 +1,228 @@
+mod admin;
+mod ansi_stream;
 mod app;
+mod audit;
+mod ban;
+mod caps;
+mod confetti;
+mod config;
+mod corners;
 
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use clap::Parser;
+use image::Rgb;
+
+use crate::firewall::Cidr;
 use crate::server::AppServer;
 
+mod firewall;
+mod flock;
+mod input;
+mod kitty;
+mod matrix;
+mod mood;
+mod particles;
+mod pong;
+mod profile;
+mod proxy_protocol;
+mod quips;
+mod rate_limit;
+mod registry;
 mod server;
+mod sftp;
+mod sixel;
+mod squash;
+mod status_bar;
+mod telnet;
+mod websocket;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "robert-ssh",
+    version,
+    about = "Serve a bouncing robert over SSH"
+)]
+pub struct Cli {
+    /// Port to listen on.
+    #[arg(long, default_value_t = 2222)]
+    pub port: u16,
+
+    /// Address to bind the listener to.
+    #[arg(long, default_value = "0.0.0.0")]
+    pub bind: IpAddr,
+
+    /// Sprite shown while robert is calm.
+    #[arg(long, default_value = "./normal.png")]
+    pub normal: String,
+
+    /// Sprite shown while robert is moving fast.
+    #[arg(long, default_value = "./scared.png")]
+    pub scared: String,
+
+    /// Sprite shown while robert is dizzy; drawn from --normal if not given.
+    #[arg(long)]
+    pub dizzy: Option<String>,
+
+    /// Sprite shown while robert is sleepy; drawn from --normal if not given.
+    #[arg(long)]
+    pub sleepy: Option<String>,
+
+    /// Sprite shown while robert is excited; drawn from --normal if not given.
+    #[arg(long)]
+    pub excited: Option<String>,
+
+    /// Maximum number of concurrent sessions; extra clients wait in line for a slot.
+    #[arg(long)]
+    pub max_sessions: Option<usize>,
+
+    /// Maximum number of simultaneous connections from a single IP address.
+    #[arg(long)]
+    pub max_per_ip: Option<usize>,
+
+    /// Only accept peers in these CIDR ranges (repeatable). Everyone is allowed when unset.
+    #[arg(long = "allow", value_name = "CIDR")]
+    pub allow: Vec<Cidr>,
+
+    /// Reject peers in these CIDR ranges (repeatable). Takes precedence over --allow.
+    #[arg(long = "deny", value_name = "CIDR")]
+    pub deny: Vec<Cidr>,
+
+    /// Ban an IP that connects more than this many times in a minute.
+    #[arg(long)]
+    pub ban_connections_per_minute: Option<usize>,
+
+    /// Ban an IP after this many failed logins in a minute.
+    #[arg(long)]
+    pub ban_auth_failures: Option<usize>,
+
+    /// How long a ban lasts, in seconds.
+    #[arg(long, default_value_t = 600)]
+    pub ban_duration_secs: u64,
+
+    /// Maximum sustained rate of accepted connections per second, across all clients.
+    #[arg(long)]
+    pub accept_rate: Option<f64>,
+
+    /// How many connections may be accepted in a burst above --accept-rate.
+    #[arg(long, default_value_t = 10.0)]
+    pub accept_burst: f64,
+
+    /// Expect a PROXY protocol (v1 or v2) header on every connection, e.g. behind HAProxy.
+    #[arg(long)]
+    pub proxy_protocol: bool,
+
+    /// Text shown to clients before authentication, e.g. "press q to quit".
+    #[arg(long)]
+    pub banner: Option<String>,
+
+    /// Warn and then disconnect clients that send no input for this many seconds.
+    #[arg(long)]
+    pub idle_timeout_secs: Option<u64>,
+
+    /// Close every session after this many seconds, so public deployments keep turning over.
+    #[arg(long)]
+    pub session_ttl_secs: Option<u64>,
+
+    /// Send an SSH keepalive after this many seconds without hearing from the client. 0
+    /// turns them off.
+    #[arg(long, default_value_t = 30)]
+    pub keepalive_secs: u64,
+
+    /// Drop the connection after this many unanswered keepalives.
+    #[arg(long, default_value_t = 3)]
+    pub keepalive_max: usize,
+
+    /// Login name that gets the session dashboard instead of robert. Only honoured for
+    /// public key logins.
+    #[arg(long, value_name = "USER")]
+    pub admin_user: Option<String>,
+
+    /// How long a broadcast notice stays on screen.
+    #[arg(long, default_value_t = 10)]
+    pub broadcast_secs: u64,
+
+    /// Also serve robert over telnet on this port, without authentication.
+    #[arg(long, value_name = "PORT")]
+    pub telnet_port: Option<u16>,
+
+    /// Also stream robert as plain ANSI on this port, for `curl` or `nc`. Frames are
+    /// 80x24 unless the request asks otherwise, as in `curl host:PORT/?cols=120&rows=40`.
+    #[arg(long, value_name = "PORT")]
+    pub ansi_port: Option<u16>,
+
+    /// Also serve robert to browsers on this port, as a web page with a terminal.
+    #[arg(long, value_name = "PORT")]
+    pub web_port: Option<u16>,
+
+    /// Frames per second each session starts at. Sessions can switch between 15, 30
+    /// and 60 with `f`.
+    #[arg(long, default_value_t = 30, value_parser = clap::value_parser!(u32).range(1..=120))]
+    pub fps: u32,
+
+    /// Draw robert in Braille dots rather than half blocks, on terminals that can:
+    /// finer detail at half the size. Sessions can switch with `b`.
+    #[arg(long)]
+    pub braille: bool,
+
+    /// Scale robert to take up this fraction of the window, between 0.1 and 1, instead
+    /// of drawing him at his natural size.
+    #[arg(long, value_name = "FRACTION", value_parser = parse_fraction)]
+    pub sprite_fraction: Option<f64>,
+
+    /// How many roberts bounce around each session. They bump off each other.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..=i64::from(app::MAX_ROBERTS)))]
+    pub roberts: u32,
+
+    /// Color treated as transparent in sprites, as RRGGBB (e.g. ff00ff), for art
+    /// without an alpha channel.
+    #[arg(long, value_name = "RRGGBB", value_parser = parse_color)]
+    pub chroma_key: Option<Rgb<u8>>,
+
+    /// Widest terminal served, in columns. Larger windows are asked to shrink.
+    #[arg(long, default_value_t = 400)]
+    pub max_cols: u16,
+
+    /// Tallest terminal served, in rows. Larger windows are asked to shrink.
+    #[arg(long, default_value_t = 200)]
+    pub max_rows: u16,
+
+    /// Widest area robert bounces in, in columns. Wider windows keep him in the middle,
+    /// so they cost no more to draw.
+    #[arg(long, default_value_t = 300)]
+    pub canvas_cols: u16,
+
+    /// Tallest area robert bounces in, in rows.
+    #[arg(long, default_value_t = 100)]
+    pub canvas_rows: u16,
+
+    /// TOML file with further settings, such as which SSH algorithms to offer.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+}
+
+fn parse_color(s: &str) -> Result<Rgb<u8>, String> {
+    let hex = s.strip_prefix('#').unwrap_or(s);
+    let value = u32::from_str_radix(hex, 16)
+        .ok()
+        .filter(|_| hex.len() == 6)
+        .ok_or_else(|| format!("{s} isn't an RRGGBB color"))?;
+    let [_, r, g, b] = value.to_be_bytes();
+    Ok(Rgb([r, g, b]))
+}
+
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    let fraction: f64 = s.parse().map_err(|e| format!("{e}"))?;
+    if !(0.1..=1.0).contains(&fraction) {
+        return Err("must be between 0.1 and 1".to_string());
+    }
+    Ok(fraction)
+}
 
 #[tokio::main]
 async fn main() {
-    let mut server = AppServer::new();
+    let cli = Cli::parse();
+    let mut server = AppServer::new(cli);
     server.run().await.expect("Failed running server");
 }
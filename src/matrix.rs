@@ -0,0 +1,83 @@
+use rand::{Rng, rngs::StdRng};
+use ratatui::{Frame, style::Color, style::Style};
+
+// Half-width katakana, which terminals draw one cell wide, plus digits.
+const GLYPHS: &[char] = &[
+    'ｦ', 'ｧ', 'ｨ', 'ｩ', 'ｪ', 'ｫ', 'ｬ', 'ｭ', 'ｮ', 'ｯ', 'ｱ', 'ｲ', 'ｳ', 'ｴ', 'ｵ', 'ｶ', 'ｷ', 'ｸ', 'ｹ',
+    'ｺ', 'ｻ', 'ｼ', 'ｽ', 'ｾ', 'ｿ', 'ﾀ', 'ﾁ', 'ﾂ', 'ﾃ', 'ﾄ', 'ﾅ', 'ﾆ', 'ﾇ', 'ﾈ', 'ﾉ', 'ﾊ', 'ﾋ', 'ﾌ',
+    'ﾍ', 'ﾎ', 'ﾏ', 'ﾐ', 'ﾑ', 'ﾒ', 'ﾓ', 'ﾔ', 'ﾕ', 'ﾖ', 'ﾗ', 'ﾘ', 'ﾙ', 'ﾚ', 'ﾛ', 'ﾜ', 'ﾝ', '0', '1',
+    '2', '3', '4', '5', '6', '7', '8', '9',
+];
+
+struct Trail {
+    // Row of the leading glyph; negative while it's still above the screen.
+    head: f64,
+    speed: f64,
+    length: u16,
+}
+
+// Falling green glyphs, one trail per column.
+#[derive(Default)]
+pub struct MatrixRain {
+    trails: Vec<Trail>,
+    height: u16,
+}
+
+impl MatrixRain {
+    // `scale` is how far to move the trails, relative to one frame at 30 FPS.
+    pub fn update(&mut self, rng: &mut StdRng, scale: f64) {
+        for trail in &mut self.trails {
+            trail.head += trail.speed * scale;
+            if trail.head - f64::from(trail.length) > f64::from(self.height) {
+                *trail = Self::new_trail(rng, self.height, false);
+            }
+        }
+    }
+
+    // Trails are dealt out afresh whenever the window changes size.
+    pub fn draw(&mut self, frame: &mut Frame, rng: &mut StdRng) {
+        let area = frame.area();
+        if self.trails.len() != usize::from(area.width) || self.height != area.height {
+            self.height = area.height;
+            self.trails = (0..area.width)
+                .map(|_| Self::new_trail(rng, area.height, true))
+                .collect();
+        }
+
+        let buffer = frame.buffer_mut();
+        for (x, trail) in self.trails.iter().enumerate() {
+            for i in 0..trail.length {
+                let y = trail.head as i32 - i32::from(i);
+                if y < 0 || y >= i32::from(area.height) {
+                    continue;
+                }
+                // White at the head, fading to dark green down the tail.
+                let color = if i == 0 {
+                    Color::Rgb(220, 255, 220)
+                } else {
+                    let fade = 255 - (u32::from(i) * 200 / u32::from(trail.length)) as u8;
+                    Color::Rgb(0, fade, 0)
+                };
+                let glyph = GLYPHS[rng.gen_range(0..GLYPHS.len())];
+                buffer[(area.x + x as u16, area.y + y as u16)]
+                    .set_char(glyph)
+                    .set_style(Style::default().fg(color));
+            }
+        }
+    }
+
+    // Trails start scattered over the screen, and restart somewhere above it.
+    fn new_trail(rng: &mut StdRng, height: u16, anywhere: bool) -> Trail {
+        let height = f64::from(height.max(1));
+        let head = if anywhere {
+            rng.gen_range(-height..height)
+        } else {
+            -rng.gen_range(0.0..height)
+        };
+        Trail {
+            head,
+            speed: rng.gen_range(0.3..1.0),
+            length: rng.gen_range(4..16),
+        }
+    }
+}
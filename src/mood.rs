@@ -0,0 +1,194 @@
+use std::time::Duration;
+
+use crate::config::ScaredConfig;
+
+// How long robert stays excited after a corner, and dizzy after turning up somewhere
+// new.
+const EXCITED_FOR: Duration = Duration::from_secs(3);
+const DIZZY_FOR: Duration = Duration::from_millis(1500);
+
+// How robert feels, which picks how he's drawn. Each has a sprite of its own, from a
+// file or drawn from the calm one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mood {
+    #[default]
+    Calm,
+    Scared,
+    Dizzy,
+    Sleepy,
+    Excited,
+}
+
+impl Mood {
+    pub const ALL: [Mood; 5] = [
+        Mood::Calm,
+        Mood::Scared,
+        Mood::Dizzy,
+        Mood::Sleepy,
+        Mood::Excited,
+    ];
+
+    // Where this mood comes in Mood::ALL.
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    // What his sprite for this mood is called over SFTP.
+    pub fn sprite_file(self) -> &'static str {
+        match self {
+            Mood::Calm => "normal.png",
+            Mood::Scared => "scared.png",
+            Mood::Dizzy => "dizzy.png",
+            Mood::Sleepy => "sleepy.png",
+            Mood::Excited => "excited.png",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Mood::Calm => "calm",
+            Mood::Scared => "scared",
+            Mood::Dizzy => "dizzy",
+            Mood::Sleepy => "sleepy",
+            Mood::Excited => "excited",
+        }
+    }
+}
+
+// What happened to a robert over one update.
+pub struct Events {
+    pub corner_hit: bool,
+    // Something gave him a fright: a burst of speed, or the mouse pointer too close.
+    pub fright: bool,
+    // Nobody's pressed anything in a while, or he's lying on the floor.
+    pub drowsy: bool,
+}
+
+// A robert's mood and how long it has left, for the ones that wear off. A fright
+// scares him for `scared.secs`, however fast he's going, after which he's back to how
+// he'd otherwise be, with the scared sprite blending out over `scared.blend_secs`. A
+// corner gets him excited, and teleporting leaves him dizzy; with nothing else going on
+// he's sleepy when drowsy and calm otherwise.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Feelings {
+    pub mood: Mood,
+    left: Duration,
+    // How much of the blend out of being scared is still to go, and how long it takes.
+    blend_left: Duration,
+    blend: Duration,
+}
+
+impl Feelings {
+    pub fn update(&mut self, dt: Duration, events: Events, scared: &ScaredConfig) {
+        self.left = self.left.saturating_sub(dt);
+        self.blend_left = self.blend_left.saturating_sub(dt);
+        if self.mood == Mood::Scared && self.left.is_zero() && !events.fright {
+            self.blend = Duration::from_secs_f64(scared.blend_secs);
+            self.blend_left = self.blend;
+            self.mood = Mood::Calm;
+        }
+        let (mood, left) = if events.fright {
+            self.blend_left = Duration::ZERO;
+            (Mood::Scared, Duration::from_secs_f64(scared.secs))
+        } else if self.mood == Mood::Scared {
+            (Mood::Scared, self.left)
+        } else if events.corner_hit {
+            (Mood::Excited, EXCITED_FOR)
+        } else if !self.left.is_zero() {
+            (self.mood, self.left)
+        } else if events.drowsy {
+            (Mood::Sleepy, Duration::ZERO)
+        } else {
+            (Mood::Calm, Duration::ZERO)
+        };
+        self.mood = mood;
+        self.left = left;
+    }
+
+    // Turning up somewhere new after a blip leaves him dizzy for a moment, whatever he
+    // was before.
+    pub fn dizzy(&mut self) {
+        self.mood = Mood::Dizzy;
+        self.left = DIZZY_FOR;
+        self.blend_left = Duration::ZERO;
+    }
+
+    // While he's blending back from being scared, how much of him is still the scared
+    // sprite, from 1 down to 0.
+    pub fn scared_share(&self) -> Option<f64> {
+        (!self.blend_left.is_zero())
+            .then(|| self.blend_left.as_secs_f64() / self.blend.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TICK: Duration = Duration::from_millis(100);
+
+    fn quiet() -> Events {
+        Events {
+            corner_hit: false,
+            fright: false,
+            drowsy: false,
+        }
+    }
+
+    fn fright() -> Events {
+        Events {
+            fright: true,
+            ..quiet()
+        }
+    }
+
+    #[test]
+    fn a_scare_wears_off_into_calm() {
+        let scared = ScaredConfig {
+            secs: 0.3,
+            blend_secs: 0.2,
+        };
+        let mut feelings = Feelings::default();
+        feelings.update(TICK, fright(), &scared);
+        assert_eq!(feelings.mood, Mood::Scared);
+        for _ in 0..3 {
+            feelings.update(TICK, quiet(), &scared);
+        }
+        assert_eq!(feelings.mood, Mood::Calm);
+        assert_eq!(feelings.scared_share(), Some(1.0));
+
+        feelings.update(TICK, quiet(), &scared);
+        assert_eq!(feelings.scared_share(), Some(0.5));
+        feelings.update(TICK, quiet(), &scared);
+        assert_eq!(feelings.mood, Mood::Calm);
+        assert_eq!(feelings.scared_share(), None);
+    }
+
+    #[test]
+    fn a_scare_wears_off_into_whatever_else_is_going_on() {
+        let scared = ScaredConfig {
+            secs: 0.1,
+            blend_secs: 0.5,
+        };
+        let mut feelings = Feelings::default();
+        feelings.update(TICK, fright(), &scared);
+        let drowsy = Events {
+            drowsy: true,
+            ..quiet()
+        };
+        feelings.update(TICK, drowsy, &scared);
+        assert_eq!(feelings.mood, Mood::Sleepy);
+        assert!(feelings.scared_share().is_some());
+    }
+
+    #[test]
+    fn teleporting_leaves_him_dizzy_for_a_while() {
+        let scared = ScaredConfig::default();
+        let mut feelings = Feelings::default();
+        feelings.dizzy();
+        feelings.update(TICK, quiet(), &scared);
+        assert_eq!(feelings.mood, Mood::Dizzy);
+        feelings.update(DIZZY_FOR, quiet(), &scared);
+        assert_eq!(feelings.mood, Mood::Calm);
+    }
+}
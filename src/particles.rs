@@ -0,0 +1,65 @@
+use std::time::{Duration, Instant};
+
+use ratatui::{buffer::Buffer, layout::Rect, style::Color};
+
+pub struct Particle {
+    // In cells from the top left of the area it's drawn in.
+    pub position: (f64, f64),
+    // Cells a frame at 30 FPS.
+    pub velocity: (f64, f64),
+    // Added to the downward velocity every frame at 30 FPS.
+    pub gravity: f64,
+    pub lifetime: Duration,
+    pub color: Color,
+    pub symbol: char,
+}
+
+// Short-lived sparks and bits that fly off and fade out.
+#[derive(Default)]
+pub struct Particles {
+    particles: Vec<(Particle, Instant)>,
+}
+
+impl Particles {
+    pub fn spawn(&mut self, particle: Particle) {
+        self.particles.push((particle, Instant::now()));
+    }
+
+    // Moves everything on, dropping what has burned out. `scale` is how far to move
+    // things, relative to one frame at 30 FPS.
+    pub fn update(&mut self, scale: f64) {
+        self.particles
+            .retain(|(particle, born)| born.elapsed() < particle.lifetime);
+        for (particle, _) in &mut self.particles {
+            particle.velocity.1 += particle.gravity * scale;
+            particle.position.0 += particle.velocity.0 * scale;
+            particle.position.1 += particle.velocity.1 * scale;
+        }
+    }
+
+    // Draws what's still burning inside `area`.
+    pub fn draw(&self, buffer: &mut Buffer, area: Rect) {
+        for (particle, born) in &self.particles {
+            let (x, y) = particle.position;
+            if x < 0.0 || y < 0.0 || x >= f64::from(area.width) || y >= f64::from(area.height) {
+                continue;
+            }
+            let left = 1.0 - born.elapsed().as_secs_f64() / particle.lifetime.as_secs_f64();
+            buffer[(area.x + x as u16, area.y + y as u16)]
+                .set_char(particle.symbol)
+                .set_fg(fade(particle.color, left));
+        }
+    }
+}
+
+// Darkens RGB colors toward black as `left` goes from 1 to 0. Named colors can't be
+// dimmed, so they stay as they are.
+fn fade(color: Color, left: f64) -> Color {
+    match color {
+        Color::Rgb(r, g, b) => {
+            let dim = |channel: u8| (f64::from(channel) * left.clamp(0.0, 1.0)) as u8;
+            Color::Rgb(dim(r), dim(g), dim(b))
+        }
+        color => color,
+    }
+}
@@ -0,0 +1,162 @@
+use rand::{Rng, rngs::StdRng};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+};
+
+const PADDLE_HEIGHT: f64 = 5.0;
+const PLAYER_STEP: f64 = 2.0;
+// How fast the computer's paddle can chase the ball, in rows per frame.
+const CPU_SPEED: f64 = 0.45;
+
+// The player on the left (w/s or arrow keys) against the computer on the right.
+#[derive(Default)]
+pub struct Pong {
+    ball: (f64, f64),
+    velocity: (f64, f64),
+    // Paddle centres, in rows.
+    player: f64,
+    cpu: f64,
+    score: (u32, u32),
+    // The player's best score across visits, when they have a profile.
+    pub best: u32,
+    size: (u16, u16),
+}
+
+impl Pong {
+    pub fn new(best: u32) -> Self {
+        Self {
+            best,
+            ..Default::default()
+        }
+    }
+
+    pub fn handle_input(&mut self, data: &[u8]) {
+        match data {
+            b"w" | b"\x1b[A" | b"\x1bOA" => self.player -= PLAYER_STEP,
+            b"s" | b"\x1b[B" | b"\x1bOB" => self.player += PLAYER_STEP,
+            _ => {}
+        }
+    }
+
+    // `scale` is how far to move things, relative to one frame at 30 FPS. Nothing
+    // moves until the court has been drawn at a size it fits.
+    pub fn update(&mut self, rng: &mut StdRng, scale: f64) {
+        if !Self::fits(self.size.0, self.size.1) {
+            return;
+        }
+        self.step(rng, scale);
+    }
+
+    // The court starts over whenever the window changes size.
+    pub fn draw(&mut self, frame: &mut Frame, rng: &mut StdRng) {
+        let area = frame.area();
+        if self.size != (area.width, area.height) {
+            self.size = (area.width, area.height);
+            self.player = f64::from(area.height) / 2.0;
+            self.cpu = self.player;
+            self.serve(rng, 1.0);
+        }
+        if !Self::fits(area.width, area.height) {
+            return;
+        }
+        self.render(frame, area);
+    }
+
+    fn fits(width: u16, height: u16) -> bool {
+        width >= 8 && f64::from(height) >= PADDLE_HEIGHT
+    }
+
+    fn serve(&mut self, rng: &mut StdRng, direction: f64) {
+        let (width, height) = (f64::from(self.size.0), f64::from(self.size.1));
+        self.ball = (width / 2.0, height / 2.0);
+        self.velocity = (direction * 0.8, rng.gen_range(-0.4..0.4));
+    }
+
+    fn step(&mut self, rng: &mut StdRng, scale: f64) {
+        let (width, height) = (f64::from(self.size.0), f64::from(self.size.1));
+        let half = PADDLE_HEIGHT / 2.0;
+        self.player = self.player.clamp(half, height - half);
+        let cpu_speed = CPU_SPEED * scale;
+        self.cpu += (self.ball.1 - self.cpu).clamp(-cpu_speed, cpu_speed);
+        self.cpu = self.cpu.clamp(half, height - half);
+
+        self.ball.0 += self.velocity.0 * scale;
+        self.ball.1 += self.velocity.1 * scale;
+
+        if self.ball.1 < 0.0 || self.ball.1 >= height {
+            self.velocity.1 = -self.velocity.1;
+            self.ball.1 = self.ball.1.clamp(0.0, height - 1.0);
+        }
+
+        // Paddles sit in the second and second-to-last columns.
+        if self.ball.0 <= 2.0 && self.velocity.0 < 0.0 {
+            if (self.ball.1 - self.player).abs() <= half {
+                self.bounce(self.player);
+            } else if self.ball.0 < 0.0 {
+                self.score.1 += 1;
+                self.serve(rng, 1.0);
+            }
+        }
+        if self.ball.0 >= width - 3.0 && self.velocity.0 > 0.0 {
+            if (self.ball.1 - self.cpu).abs() <= half {
+                self.bounce(self.cpu);
+            } else if self.ball.0 >= width {
+                self.score.0 += 1;
+                self.best = self.best.max(self.score.0);
+                self.serve(rng, -1.0);
+            }
+        }
+    }
+
+    // Sends the ball back, angled by where it hit the paddle.
+    fn bounce(&mut self, paddle: f64) {
+        let hit = (self.ball.1 - paddle) / (PADDLE_HEIGHT / 2.0);
+        self.velocity.0 = -self.velocity.0 * 1.05;
+        self.velocity.0 = self.velocity.0.clamp(-2.0, 2.0);
+        self.velocity.1 = hit * 0.6;
+    }
+
+    fn render(&self, frame: &mut Frame, area: Rect) {
+        let buffer = frame.buffer_mut();
+        let style = Style::default().fg(Color::White);
+        let dim = Style::default().fg(Color::DarkGray);
+
+        for y in area.top()..area.bottom() {
+            if y % 2 == 0 {
+                buffer.set_string(area.x + area.width / 2, y, "┆", dim);
+            }
+        }
+
+        let half = PADDLE_HEIGHT / 2.0;
+        for (x, centre) in [(area.x + 1, self.player), (area.right() - 2, self.cpu)] {
+            let top = (centre - half).round() as u16;
+            for y in top..(top + PADDLE_HEIGHT as u16).min(area.height) {
+                buffer.set_string(x, area.y + y, "█", style);
+            }
+        }
+
+        let score = format!("{}   {}", self.score.0, self.score.1);
+        if self.best > 0 {
+            let best = format!("best {}", self.best);
+            buffer.set_string(area.x + 1, area.y, best, dim);
+        }
+        let score_x = area.x + (area.width.saturating_sub(score.len() as u16)) / 2;
+        buffer.set_string(score_x, area.y, score, style);
+
+        let help = "w/s or ↑/↓ to move, q to quit";
+        let help_x = area.x + (area.width.saturating_sub(help.chars().count() as u16)) / 2;
+        buffer.set_string(help_x, area.bottom() - 1, help, dim);
+
+        let (x, y) = (self.ball.0 as u16, self.ball.1 as u16);
+        if x < area.width && y < area.height {
+            buffer.set_string(
+                area.x + x,
+                area.y + y,
+                "●",
+                Style::default().fg(Color::Yellow),
+            );
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::app::{SPEED_RANGE, Scene};
+use crate::config::Theme;
+
+// What we remember about a public key between visits. The theme and speed are None for
+// profiles saved before they were, which get the server's defaults.
+#[derive(Clone, Copy)]
+pub struct Profile {
+    pub scene: Scene,
+    pub high_score: u32,
+    pub theme: Option<Theme>,
+    pub speed: Option<f64>,
+}
+
+// Profiles by key fingerprint, kept in a plain text file with one line per key:
+// `SHA256:... scene=pong high_score=3 theme=high-contrast speed=1.5`.
+pub struct ProfileStore {
+    path: PathBuf,
+    profiles: HashMap<String, Profile>,
+}
+
+impl ProfileStore {
+    // A missing file is an empty store; it gets created on the first save.
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut profiles = HashMap::new();
+        for line in contents.lines() {
+            let mut fields = line.split_whitespace();
+            let Some(fingerprint) = fields.next() else {
+                continue;
+            };
+
+            let mut profile = Profile {
+                scene: Scene::Splash,
+                high_score: 0,
+                theme: None,
+                speed: None,
+            };
+            for field in fields {
+                match field.split_once('=') {
+                    Some(("scene", name)) => {
+                        profile.scene = Scene::from_name(name).unwrap_or(Scene::Splash);
+                    }
+                    Some(("high_score", score)) => {
+                        profile.high_score = score.parse().unwrap_or(0);
+                    }
+                    Some(("theme", name)) => profile.theme = Theme::from_name(name),
+                    Some(("speed", speed)) => {
+                        profile.speed = speed
+                            .parse()
+                            .ok()
+                            .filter(|speed| SPEED_RANGE.contains(speed));
+                    }
+                    _ => {}
+                }
+            }
+            profiles.insert(fingerprint.to_string(), profile);
+        }
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            profiles,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.profiles.len()
+    }
+
+    pub fn get(&self, fingerprint: &str) -> Option<Profile> {
+        self.profiles.get(fingerprint).copied()
+    }
+
+    pub fn update(&mut self, fingerprint: &str, profile: Profile) -> Result<(), anyhow::Error> {
+        self.profiles.insert(fingerprint.to_string(), profile);
+
+        let mut contents = String::new();
+        for (fingerprint, profile) in &self.profiles {
+            contents.push_str(&format!(
+                "{} scene={} high_score={}",
+                fingerprint,
+                profile.scene.name(),
+                profile.high_score
+            ));
+            if let Some(theme) = profile.theme {
+                contents.push_str(&format!(" theme={}", theme.name()));
+            }
+            if let Some(speed) = profile.speed {
+                contents.push_str(&format!(" speed={speed}"));
+            }
+            contents.push('\n');
+        }
+
+        // Written next to the real file and renamed over it, so a crash can't truncate it.
+        let temp_path = self.path.with_extension("tmp");
+        std::fs::write(&temp_path, contents)?;
+        std::fs::rename(&temp_path, &self.path)?;
+        Ok(())
+    }
+}
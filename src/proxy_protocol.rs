@@ -0,0 +1,209 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+// Longest possible v1 header, including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+
+// Reads a PROXY protocol v1 or v2 header off the front of the stream, consuming exactly
+// the header bytes. Returns the original client address, or None for LOCAL/UNKNOWN
+// headers where the connection's own peer address should be used.
+pub async fn read_header<R: AsyncRead + Unpin>(
+    stream: &mut R,
+) -> Result<Option<SocketAddr>, anyhow::Error> {
+    let mut prefix = [0u8; 12];
+    stream.read_exact(&mut prefix).await?;
+
+    if &prefix == V2_SIGNATURE {
+        read_v2(stream).await
+    } else if prefix.starts_with(b"PROXY ") {
+        read_v1(stream, &prefix).await
+    } else {
+        Err(anyhow::anyhow!("Missing PROXY protocol header"))
+    }
+}
+
+async fn read_v1<R: AsyncRead + Unpin>(
+    stream: &mut R,
+    prefix: &[u8],
+) -> Result<Option<SocketAddr>, anyhow::Error> {
+    let mut line = prefix.to_vec();
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            return Err(anyhow::anyhow!("PROXY v1 header is too long"));
+        }
+        line.push(stream.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])?;
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "TCP4" | "TCP6", source, _, source_port, _] => {
+            let ip: IpAddr = source.parse()?;
+            let port: u16 = source_port.parse()?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        _ => Err(anyhow::anyhow!("Malformed PROXY v1 header: {line}")),
+    }
+}
+
+async fn read_v2<R: AsyncRead + Unpin>(
+    stream: &mut R,
+) -> Result<Option<SocketAddr>, anyhow::Error> {
+    let version_command = stream.read_u8().await?;
+    let family = stream.read_u8().await?;
+    let len = usize::from(stream.read_u16().await?);
+
+    let mut addresses = vec![0u8; len];
+    stream.read_exact(&mut addresses).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(anyhow::anyhow!("Unsupported PROXY protocol version"));
+    }
+    // LOCAL command: health checks from the balancer itself.
+    if version_command & 0x0f == 0 {
+        return Ok(None);
+    }
+
+    // Anything the balancer says the client is has to be used, or the per-address limits,
+    // bans and rules would fall on the balancer; only an unspecified address means there's
+    // no client address to go by.
+    match family {
+        0x00 => Ok(None),
+        // TCP over IPv4
+        0x11 if len >= 12 => {
+            let ip = Ipv4Addr::new(addresses[0], addresses[1], addresses[2], addresses[3]);
+            let port = u16::from_be_bytes([addresses[8], addresses[9]]);
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        // TCP over IPv6
+        0x21 if len >= 36 => {
+            let octets: [u8; 16] = addresses[..16].try_into()?;
+            let port = u16::from_be_bytes([addresses[32], addresses[33]]);
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        0x11 | 0x21 => Err(anyhow::anyhow!(
+            "PROXY protocol v2 addresses too short for their family"
+        )),
+        _ => Err(anyhow::anyhow!(
+            "Unsupported PROXY protocol v2 address family {family:#04x}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads a header off `input`, returning it along with whatever was left unread.
+    async fn read(input: &[u8]) -> (Result<Option<SocketAddr>, anyhow::Error>, Vec<u8>) {
+        let mut stream = input;
+        let header = read_header(&mut stream).await;
+        (header, stream.to_vec())
+    }
+
+    fn v2(version_command: u8, family: u8, addresses: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.extend([version_command, family]);
+        header.extend((addresses.len() as u16).to_be_bytes());
+        header.extend(addresses);
+        header
+    }
+
+    #[tokio::test]
+    async fn reads_v1_headers() {
+        let (header, rest) = read(b"PROXY TCP4 203.0.113.7 192.0.2.1 51234 22\r\nSSH-2.0").await;
+        assert_eq!(header.unwrap(), Some("203.0.113.7:51234".parse().unwrap()));
+        assert_eq!(rest, b"SSH-2.0");
+
+        let (header, _) = read(b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 22\r\n").await;
+        assert_eq!(header.unwrap(), Some("[2001:db8::1]:4000".parse().unwrap()));
+
+        let (header, rest) = read(b"PROXY UNKNOWN\r\nSSH").await;
+        assert_eq!(header.unwrap(), None);
+        assert_eq!(rest, b"SSH");
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_v1_headers() {
+        for bad in [
+            &b"PROXY TCP4 203.0.113.7 192.0.2.1 51234"[..],
+            b"PROXY TCP4 203.0.113.7 192.0.2.1 51234 22",
+            b"PROXY TCP4 not.an.ip 192.0.2.1 51234 22\r\n",
+            b"PROXY TCP4 203.0.113.7 192.0.2.1 99999 22\r\n",
+            b"PROXY UDP4 203.0.113.7 192.0.2.1 51234 22\r\n",
+            b"SSH-2.0-OpenSSH_9.6\r\n",
+            b"PROX",
+        ] {
+            assert!(
+                read(bad).await.0.is_err(),
+                "{:?} was accepted",
+                String::from_utf8_lossy(bad)
+            );
+        }
+
+        let mut endless = b"PROXY ".to_vec();
+        endless.resize(500, b'x');
+        assert!(read(&endless).await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn reads_v2_headers() {
+        let mut addresses = vec![203, 0, 113, 7, 192, 0, 2, 1];
+        addresses.extend(51234u16.to_be_bytes());
+        addresses.extend(22u16.to_be_bytes());
+        let mut input = v2(0x21, 0x11, &addresses);
+        input.extend(b"SSH");
+        let (header, rest) = read(&input).await;
+        assert_eq!(header.unwrap(), Some("203.0.113.7:51234".parse().unwrap()));
+        assert_eq!(rest, b"SSH");
+
+        let mut addresses = Ipv6Addr::LOCALHOST.octets().to_vec();
+        addresses.extend([0; 16]);
+        addresses.extend(4000u16.to_be_bytes());
+        addresses.extend(22u16.to_be_bytes());
+        let (header, _) = read(&v2(0x21, 0x21, &addresses)).await;
+        assert_eq!(header.unwrap(), Some("[::1]:4000".parse().unwrap()));
+    }
+
+    #[tokio::test]
+    async fn v2_local_and_unspecified_addresses_use_the_peer_address() {
+        let (header, rest) = read(&[v2(0x20, 0x11, &[0; 12]), b"SSH".to_vec()].concat()).await;
+        assert_eq!(header.unwrap(), None);
+        assert_eq!(rest, b"SSH");
+
+        let (header, rest) = read(&[v2(0x21, 0x00, &[]), b"SSH".to_vec()].concat()).await;
+        assert_eq!(header.unwrap(), None);
+        assert_eq!(rest, b"SSH");
+    }
+
+    #[tokio::test]
+    async fn rejects_v2_addresses_too_short_for_their_family() {
+        assert!(read(&v2(0x21, 0x11, &[0; 4])).await.0.is_err());
+        assert!(read(&v2(0x21, 0x11, &[0; 11])).await.0.is_err());
+        assert!(read(&v2(0x21, 0x21, &[0; 12])).await.0.is_err());
+        assert!(read(&v2(0x21, 0x21, &[0; 35])).await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_v2_families_other_than_tcp() {
+        assert!(read(&v2(0x21, 0x12, &[0; 12])).await.0.is_err());
+        assert!(read(&v2(0x21, 0x31, &[0; 216])).await.0.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_bad_v2_headers() {
+        let (header, _) = read(&v2(0x31, 0x11, &[0; 12])).await;
+        assert!(header.is_err());
+
+        let mut truncated = v2(0x21, 0x11, &[0; 12]);
+        truncated.truncate(20);
+        assert!(read(&truncated).await.0.is_err());
+
+        let mut short_length = V2_SIGNATURE.to_vec();
+        short_length.extend([0x21, 0x11, 0x00]);
+        assert!(read(&short_length).await.0.is_err());
+    }
+}
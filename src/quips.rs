@@ -0,0 +1,45 @@
+use std::time::{Duration, Instant};
+
+use rand::{Rng, rngs::StdRng};
+
+use crate::config::QuipsConfig;
+
+// Picks when robert says something, and what.
+pub struct Quips {
+    config: QuipsConfig,
+    next_at: Instant,
+    // The message on screen and when it comes down.
+    showing: Option<(usize, Instant)>,
+}
+
+impl Quips {
+    pub fn new(config: QuipsConfig, rng: &mut StdRng) -> Self {
+        let next_at = Instant::now() + Self::gap(&config, rng);
+        Self {
+            config,
+            next_at,
+            showing: None,
+        }
+    }
+
+    // Somewhere between half and one and a half times `every_secs`, so it doesn't
+    // feel like clockwork.
+    fn gap(config: &QuipsConfig, rng: &mut StdRng) -> Duration {
+        Duration::from_secs_f64(config.every_secs * rng.gen_range(0.5..1.5))
+    }
+
+    // What robert is saying right now, if anything.
+    pub fn current(&mut self, rng: &mut StdRng) -> Option<&str> {
+        let now = Instant::now();
+        if self.showing.is_some_and(|(_, until)| until <= now) {
+            self.showing = None;
+            self.next_at = now + Self::gap(&self.config, rng);
+        }
+        if self.showing.is_none() && now >= self.next_at && !self.config.messages.is_empty() {
+            let index = rng.gen_range(0..self.config.messages.len());
+            self.showing = Some((index, now + Duration::from_secs_f64(self.config.show_secs)));
+        }
+        self.showing
+            .map(|(index, _)| self.config.messages[index].as_str())
+    }
+}
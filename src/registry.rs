@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use crate::caps::ClientCaps;
+
+// Live numbers for one session, shared between its client and the registry.
+pub struct SessionStats {
+    pub id: usize,
+    pub peer_addr: Option<SocketAddr>,
+    pub user: String,
+    pub started_at: Instant,
+    pub bytes_sent: Arc<AtomicU64>,
+    caps: Mutex<ClientCaps>,
+    // Frames drawn since the window started, and the rate measured over the last one.
+    frames: Mutex<(Instant, u32, f64)>,
+    // How long frames take to draw and hand off, averaged.
+    frame_micros: AtomicU64,
+}
+
+impl SessionStats {
+    pub fn record_frame(&self, took: Duration) {
+        let sample = took.as_micros() as u64;
+        let average = self.frame_micros.load(Ordering::Relaxed);
+        self.frame_micros
+            .store((average * 7 + sample) / 8, Ordering::Relaxed);
+
+        let mut frames = self.frames.lock().unwrap();
+        frames.1 += 1;
+        let elapsed = frames.0.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            *frames = (
+                Instant::now(),
+                0,
+                f64::from(frames.1) / elapsed.as_secs_f64(),
+            );
+        }
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.frames.lock().unwrap().2
+    }
+
+    pub fn frame_time(&self) -> Duration {
+        Duration::from_micros(self.frame_micros.load(Ordering::Relaxed))
+    }
+
+    pub fn caps(&self) -> ClientCaps {
+        self.caps.lock().unwrap().clone()
+    }
+
+    pub fn set_caps(&self, caps: ClientCaps) {
+        *self.caps.lock().unwrap() = caps;
+    }
+}
+
+// Every interactive session on the server. Entries go away on their own once the
+// session's client drops its stats.
+#[derive(Clone, Default)]
+pub struct SessionRegistry {
+    sessions: Arc<Mutex<BTreeMap<usize, Weak<SessionStats>>>>,
+    // Message shown on top of every session's frame, and when it comes down.
+    broadcast: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+impl SessionRegistry {
+    pub fn register(
+        &self,
+        id: usize,
+        peer_addr: Option<SocketAddr>,
+        user: &str,
+        bytes_sent: Arc<AtomicU64>,
+    ) -> Arc<SessionStats> {
+        let stats = Arc::new(SessionStats {
+            id,
+            peer_addr,
+            user: user.to_string(),
+            started_at: Instant::now(),
+            bytes_sent,
+            caps: Mutex::new(ClientCaps::default()),
+            frames: Mutex::new((Instant::now(), 0, 0.0)),
+            frame_micros: AtomicU64::new(0),
+        });
+        self.sessions
+            .lock()
+            .unwrap()
+            .insert(id, Arc::downgrade(&stats));
+        stats
+    }
+
+    // Live sessions, oldest first.
+    pub fn list(&self) -> Vec<Arc<SessionStats>> {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|_, stats| stats.strong_count() > 0);
+        sessions.values().filter_map(Weak::upgrade).collect()
+    }
+
+    // Puts `message` up on every session for `duration`, replacing any earlier one.
+    pub fn broadcast(&self, message: &str, duration: Duration) {
+        println!("Broadcasting for {}s: {message}", duration.as_secs());
+        *self.broadcast.lock().unwrap() = Some((message.to_string(), Instant::now() + duration));
+    }
+
+    pub fn current_broadcast(&self) -> Option<String> {
+        let mut broadcast = self.broadcast.lock().unwrap();
+        if broadcast
+            .as_ref()
+            .is_some_and(|(_, until)| *until <= Instant::now())
+        {
+            *broadcast = None;
+        }
+        broadcast.as_ref().map(|(message, _)| message.clone())
+    }
+}
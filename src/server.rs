@@ -1,183 +1,2169 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Weak};
 
-use rand::SeedableRng;
-use rand::rngs::StdRng;
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use chrono::Datelike;
+use futures_util::SinkExt;
+use rand::rngs::{OsRng, StdRng};
+use rand::{Rng, SeedableRng};
 use ratatui::backend::CrosstermBackend;
+use ratatui::buffer::Buffer;
 use ratatui::layout::Rect;
 use ratatui::{Terminal, TerminalOptions, Viewport};
-use russh::{Channel, ChannelId, Pty};
+use russh::keys::ssh_key::certificate::CertType;
+use russh::keys::ssh_key::{AuthorizedKeys, Certificate, Fingerprint, LineEnding};
+use russh::keys::{Algorithm, HashAlg, PublicKey};
+use russh::{Channel, ChannelId, Disconnect, Pty};
 use russh::{MethodKind, MethodSet, server::*};
-use tokio::sync::Mutex;
-use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::unbounded_channel;
+use tokio::sync::{Mutex, Notify, broadcast};
+use tokio::task::{AbortHandle, JoinSet};
+use tokio::time::MissedTickBehavior;
+use tokio_tungstenite::tungstenite::Message;
 
-use crate::app::{App, load_to_pixel_map};
+use crate::Cli;
+use crate::admin::AdminPanel;
+use crate::ansi_stream;
+use crate::app::{
+    App, Marquee, MoodSprites, REDUCED_MOTION_SPEED, Scene, Season, Sprite, TICK_RATE,
+    ansi_snapshot, draw_broadcast, draw_goodbye, draw_too_large, draw_waiting_room,
+    load_to_pixel_map, mood_sprite, pixel_map_from_png, to_pixel_map,
+};
+use crate::audit::{AuditEvent, AuditLog};
+use crate::ban::BanTracker;
+use crate::caps::{ClientCaps, TERMINAL_QUERIES};
+use crate::config::{ConfigFile, Theme};
+use crate::corners::CornerCounter;
+use crate::firewall::Firewall;
+use crate::flock::Flock;
+use crate::input::{InputEvent, InputSource};
+use crate::matrix::MatrixRain;
+use crate::mood::Mood;
+use crate::particles::Particles;
+use crate::pong::Pong;
+use crate::profile::{Profile, ProfileStore};
+use crate::proxy_protocol;
+use crate::quips::Quips;
+use crate::rate_limit::TokenBucket;
+use crate::registry::{SessionRegistry, SessionStats};
+use crate::sftp::{SpriteSftp, Uploader, Uploads};
+use crate::status_bar::StatusBar;
+use crate::telnet;
+use crate::websocket;
 
 const ENTER_ALT_SCREEN: &[u8] = b"\x1b[?1049h";
 const EXIT_ALT_SCREEN: &[u8] = b"\x1b[?1049l";
+const CLEAR_SCREEN: &[u8] = b"\x1b[2J";
 const HIDE_CURSOR: &[u8] = b"\x1b[?25l";
 const SHOW_CURSOR: &[u8] = b"\x1b[?25h";
+// Saves the window title on the terminal's title stack and puts ours up. Terminals
+// without the stack get a blank title back when the session ends instead.
+const SET_TITLE: &[u8] = b"\x1b[22;0t\x1b]0;robert-ssh - press q to quit\x07";
+const RESTORE_TITLE: &[u8] = b"\x1b]0;\x07\x1b[23;0t";
+const BEL: u8 = 0x07;
+// Every pointer movement, reported in the SGR encoding, while robert follows the mouse.
+const MOUSE_ON: &[u8] = b"\x1b[?1003h\x1b[?1006h";
+const MOUSE_OFF: &[u8] = b"\x1b[?1003l\x1b[?1006l";
+// How long a new telnet or browser client gets to report its window size.
+const FIRST_SIZE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
+// A client that can't take a frame within this long is treated as dead.
+const SEND_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+// Frames a session can have waiting to go out before the backlog is thrown away.
+const MAX_QUEUED_FRAMES: usize = 8;
+// How often a session's frame rate is reconsidered, and how low it can be taken.
+const ADAPT_EVERY: std::time::Duration = std::time::Duration::from_secs(1);
+const MIN_ADAPTIVE_FPS: u32 = 5;
+// A new window size is only used once the client has stopped sending them for this
+// long, so dragging a window edge doesn't redraw the whole screen at every step.
+const RESIZE_QUIET: std::time::Duration = std::time::Duration::from_millis(100);
 
 type SshTerminal = Terminal<CrosstermBackend<TerminalHandle>>;
 
+// The ways in besides SSH, none of which ask for a login.
+#[derive(Clone, Copy, Debug)]
+enum Transport {
+    Telnet,
+    Ansi,
+    Web,
+}
+
+#[derive(Default)]
+struct Listeners {
+    telnet: Option<TcpListener>,
+    ansi: Option<TcpListener>,
+    web: Option<TcpListener>,
+}
+
+impl Listeners {
+    async fn accept(&self) -> (Transport, std::io::Result<(TcpStream, SocketAddr)>) {
+        tokio::select! {
+            result = accept(&self.telnet) => (Transport::Telnet, result),
+            result = accept(&self.ansi) => (Transport::Ansi, result),
+            result = accept(&self.web) => (Transport::Web, result),
+        }
+    }
+}
+
+// Where a session's bytes go.
+#[derive(Clone)]
+enum Output {
+    Ssh(Handle, ChannelId),
+    // Behind a mutex so frames and the final reset sequence can't interleave.
+    Telnet(Arc<Mutex<OwnedWriteHalf>>),
+    // Plain TCP, for curl and nc.
+    Raw(Arc<Mutex<OwnedWriteHalf>>),
+    WebSocket(Arc<Mutex<websocket::Sink>>),
+}
+
+impl Output {
+    async fn send(&self, data: Vec<u8>) -> Result<(), anyhow::Error> {
+        match self {
+            Output::Ssh(handle, channel_id) => handle
+                .data(*channel_id, data.into())
+                .await
+                .map_err(|_| anyhow::anyhow!("channel closed")),
+            Output::Telnet(writer) => {
+                writer
+                    .lock()
+                    .await
+                    .write_all(&telnet::escape(&data))
+                    .await?;
+                Ok(())
+            }
+            Output::Raw(writer) => {
+                writer.lock().await.write_all(&data).await?;
+                Ok(())
+            }
+            Output::WebSocket(sink) => {
+                sink.lock().await.send(Message::Binary(data.into())).await?;
+                Ok(())
+            }
+        }
+    }
+
+    async fn close(&self) {
+        match self {
+            Output::Ssh(handle, channel_id) => {
+                let _ = handle.close(*channel_id).await;
+            }
+            Output::Telnet(writer) | Output::Raw(writer) => {
+                let _ = writer.lock().await.shutdown().await;
+            }
+            Output::WebSocket(sink) => {
+                let _ = sink.lock().await.close().await;
+            }
+        }
+    }
+}
+
+// Frames on their way to the client. Bounded, so a slow link can't make them pile up.
+// Each frame only draws what changed since the one before, so dropping the oldest means
+// dropping back to the newest full redraw still queued, or, failing that, dropping the
+// lot and asking the session for a new one.
+#[derive(Default)]
+struct FrameQueue {
+    inner: std::sync::Mutex<QueuedFrames>,
+    ready: Notify,
+    // How long a send has been taking, averaged over the last several, in microseconds.
+    send_micros: AtomicU64,
+}
+
+#[derive(Default)]
+struct QueuedFrames {
+    // Each frame, and whether it starts a full redraw.
+    frames: VecDeque<(Vec<u8>, bool)>,
+    sync: FrameSync,
+    // The next frame only repeats what the client already has.
+    skip_next: bool,
+}
+
+#[derive(Default, PartialEq)]
+enum FrameSync {
+    #[default]
+    InSync,
+    // Frames were dropped; anything until the redraw would be drawn on the wrong base.
+    NeedsRedraw,
+    // The next frame is the start of the redraw.
+    Redrawing,
+}
+
+impl FrameQueue {
+    fn push(&self, mut frame: Vec<u8>) {
+        {
+            let mut queued = self.inner.lock().unwrap();
+            if std::mem::take(&mut queued.skip_next) {
+                return;
+            }
+            let redraw = match queued.sync {
+                FrameSync::NeedsRedraw => return,
+                // The client's screen is unknown by now, so it's wiped first.
+                FrameSync::Redrawing => {
+                    queued.sync = FrameSync::InSync;
+                    frame.splice(0..0, CLEAR_SCREEN.iter().copied());
+                    true
+                }
+                FrameSync::InSync => false,
+            };
+
+            if queued.frames.len() >= MAX_QUEUED_FRAMES {
+                if let Some(start) = queued.frames.iter().rposition(|(_, redraw)| *redraw) {
+                    queued.frames.drain(..start);
+                }
+                if queued.frames.len() >= MAX_QUEUED_FRAMES {
+                    queued.frames.clear();
+                    queued.sync = FrameSync::NeedsRedraw;
+                    return;
+                }
+            }
+            queued.frames.push_back((frame, redraw));
+        }
+        self.ready.notify_one();
+    }
+
+    async fn pop(&self) -> Vec<u8> {
+        loop {
+            if let Some((frame, _)) = self.inner.lock().unwrap().frames.pop_front() {
+                return frame;
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    fn skip_next(&self) {
+        self.inner.lock().unwrap().skip_next = true;
+    }
+
+    fn record_send(&self, took: std::time::Duration) {
+        let sample = took.as_micros() as u64;
+        let average = self.send_micros.load(Ordering::Relaxed);
+        let average = (average * 7 + sample) / 8;
+        self.send_micros.store(average, Ordering::Relaxed);
+    }
+
+    fn send_latency(&self) -> std::time::Duration {
+        std::time::Duration::from_micros(self.send_micros.load(Ordering::Relaxed))
+    }
+
+    // True once after frames were dropped, in which case the next frame has to draw
+    // the whole screen.
+    fn take_needs_redraw(&self) -> bool {
+        let mut queued = self.inner.lock().unwrap();
+        if queued.sync != FrameSync::NeedsRedraw {
+            return false;
+        }
+        queued.sync = FrameSync::Redrawing;
+        true
+    }
+}
+
 struct TerminalHandle {
-    sender: UnboundedSender<Vec<u8>>,
+    frames: Arc<FrameQueue>,
     sink: Vec<u8>,
+    // Cleared by the forwarding task once the channel stops accepting data.
+    alive: Arc<AtomicBool>,
+    bytes_sent: Arc<AtomicU64>,
+    forwarder: AbortHandle,
+}
+
+impl TerminalHandle {
+    // `bytes_sent` counts what actually reached the client.
+    async fn start(output: Output, bytes_sent: Arc<AtomicU64>) -> Self {
+        let frames = Arc::new(FrameQueue::default());
+        let task_frames = frames.clone();
+        let alive = Arc::new(AtomicBool::new(true));
+        let task_alive = alive.clone();
+        let task_bytes_sent = bytes_sent.clone();
+        let forwarder = tokio::spawn(async move {
+            loop {
+                let data = task_frames.pop().await;
+                let len = data.len() as u64;
+                let started = std::time::Instant::now();
+                match tokio::time::timeout(SEND_TIMEOUT, output.send(data)).await {
+                    Ok(Ok(())) => {
+                        task_frames.record_send(started.elapsed());
+                        task_bytes_sent.fetch_add(len, Ordering::Relaxed);
+                    }
+                    Ok(Err(_)) => {
+                        eprintln!("Failed to send data: channel closed");
+                        break;
+                    }
+                    Err(_) => {
+                        eprintln!("Failed to send data: client stopped responding");
+                        break;
+                    }
+                }
+            }
+            task_alive.store(false, Ordering::Relaxed);
+        });
+        Self {
+            frames,
+            sink: Vec::new(),
+            alive,
+            bytes_sent,
+            forwarder: forwarder.abort_handle(),
+        }
+    }
+}
+
+// The crossterm backend writes to the terminal handle.
+impl std::io::Write for TerminalHandle {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.sink.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.alive.load(Ordering::Relaxed) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "channel closed",
+            ));
+        }
+
+        self.frames.push(std::mem::take(&mut self.sink));
+        Ok(())
+    }
+}
+
+// The forwarding task holds a clone of the session handle, so it's stopped as soon as
+// the terminal goes rather than whenever its queue happens to drain.
+impl Drop for TerminalHandle {
+    fn drop(&mut self) {
+        self.forwarder.abort();
+    }
+}
+
+struct Client {
+    terminal: SshTerminal,
+    app: App,
+    output: Output,
+    alive: Arc<AtomicBool>,
+    // Whose uploaded sprites the session shows.
+    uploader: Uploader,
+    // Where to save this session's profile when it ends, if it has one.
+    profile: Option<(String, Arc<std::sync::Mutex<ProfileStore>>)>,
+    stats: Arc<SessionStats>,
+    forwarder: AbortHandle,
+    frames: Arc<FrameQueue>,
+    // The session's own render task, stopped when the session is dropped.
+    render: Option<AbortHandle>,
+    // Set while the window is bigger than --max-cols/--max-rows.
+    too_large: bool,
+    adapted_at: std::time::Instant,
+    // The latest size the client reported, whether it's too large, and when it came.
+    pending_resize: Option<(Rect, bool, std::time::Instant)>,
+    // What the client's screen shows, so a tick that changes nothing sends nothing.
+    last_frame: Buffer,
+    // Set for the `robert` subsystem, whose frames go out as lines of JSON instead of
+    // being drawn: the number of the next one.
+    json_frame: Option<u64>,
+}
+
+// Sessions are locked one at a time, so a slow one only holds itself up.
+type SharedClient = Arc<Mutex<Client>>;
+
+impl Client {
+    fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::Relaxed)
+    }
+
+    // Sessions in the waiting room don't time out, since they can't do anything yet.
+    fn is_finished(&self, waiting: bool) -> bool {
+        !self.is_alive() || (!waiting && (self.app.is_idle_expired() || self.app.is_ttl_expired()))
+    }
+
+    // Eases the frame rate down while sends take most of a frame, and back up once
+    // they're quick again. Only reconsidered every ADAPT_EVERY, so it doesn't hunt.
+    fn adapt_frame_rate(&mut self) {
+        if self.adapted_at.elapsed() < ADAPT_EVERY {
+            return;
+        }
+        self.adapted_at = std::time::Instant::now();
+
+        let fps = self.app.effective_fps();
+        let latency = self.frames.send_latency();
+        let period = std::time::Duration::from_secs(1) / fps;
+        if latency > period * 3 / 4 && fps > MIN_ADAPTIVE_FPS {
+            self.app.fps_cap = (fps / 2).max(MIN_ADAPTIVE_FPS);
+        } else if latency < period / 4 && fps < self.app.fps {
+            self.app.fps_cap = fps + (fps / 4).max(1);
+        }
+    }
+
+    // Takes up the pending size once RESIZE_QUIET has passed without another.
+    fn settle_resize(&mut self) -> std::io::Result<()> {
+        let Some((rect, too_large, at)) = self.pending_resize else {
+            return Ok(());
+        };
+        if at.elapsed() < RESIZE_QUIET {
+            return Ok(());
+        }
+        self.pending_resize = None;
+        self.terminal.resize(rect)?;
+        self.too_large = too_large;
+        // The resize wiped the client's screen.
+        self.last_frame = Buffer::default();
+        Ok(())
+    }
+
+    // Restores the client's terminal and closes the channel from the server side.
+    async fn close(&self) {
+        if self.json_frame.is_none() {
+            let reset_sequence = [EXIT_ALT_SCREEN, SHOW_CURSOR, RESTORE_TITLE, MOUSE_OFF].concat();
+            let _ = self.output.send(reset_sequence).await;
+        }
+        self.output.close().await;
+    }
+
+    // Ends the session from the server side. Frames still queued are dropped rather
+    // than sent after the reset sequence.
+    async fn terminate(&self) {
+        self.forwarder.abort();
+        self.close().await;
+    }
+}
+
+// However a session ends, its profile is saved on the way out.
+impl Drop for Client {
+    fn drop(&mut self) {
+        if let Some(render) = &self.render {
+            render.abort();
+        }
+
+        let Some((fingerprint, profiles)) = &self.profile else {
+            return;
+        };
+        let theme = if self.app.high_contrast {
+            Theme::HighContrast
+        } else {
+            Theme::Normal
+        };
+        let profile = Profile {
+            scene: self.app.scene,
+            high_score: self.app.pong.best,
+            theme: Some(theme),
+            speed: Some(self.app.speed),
+        };
+        if let Err(e) = profiles.lock().unwrap().update(fingerprint, profile) {
+            eprintln!("Failed to save profile: {e}");
+        }
+    }
+}
+
+// Listens for connections and hands each one its own `ConnectionHandler`.
+pub struct AppServer {
+    cli: Arc<Cli>,
+    next_id: usize,
+}
+
+// Everything the connections of a running server share.
+struct ServerState {
+    cli: Arc<Cli>,
+    clients: Mutex<HashMap<usize, SharedClient>>,
+    // None means anonymous mode: everyone is let in through `auth_none`.
+    authorized_keys: Option<Vec<PublicKey>>,
+    // Fingerprints of the CAs whose user certificates are accepted.
+    trusted_cas: Option<Vec<Fingerprint>>,
+    // Username to argon2 PHC hash string.
+    credentials: Option<HashMap<String, String>>,
+    // Open connections per source address. A std mutex because it's updated from `Drop`.
+    connections_per_ip: std::sync::Mutex<HashMap<IpAddr, usize>>,
+    firewall: Firewall,
+    bans: std::sync::Mutex<BanTracker>,
+    started_at: std::time::Instant,
+    uploads: Uploads,
+    // The sprite for each mood, from its option or drawn from --normal, decoded once at
+    // startup and shared.
+    default_sprites: MoodSprites,
+    profiles: Option<Arc<std::sync::Mutex<ProfileStore>>>,
+    registry: SessionRegistry,
+    // Pinged by the admin `drain` command.
+    drain: Notify,
+    config_file: ConfigFile,
+    audit_log: Option<AuditLog>,
+    corner_counter: Arc<CornerCounter>,
+    // Sessions over `max_sessions`, in the order they arrived. They're in `clients` too,
+    // but shown the waiting room until they reach the front and a slot frees up.
+    waiting: std::sync::Mutex<VecDeque<usize>>,
+}
+
+// The russh handler for a single connection, created when it's accepted.
+struct ConnectionHandler {
+    id: usize,
+    state: Arc<ServerState>,
+    pty_requested: bool,
+    // The channel the interactive session runs on, once a pty is requested.
+    terminal_channel: Option<ChannelId>,
+    peer_addr: Option<SocketAddr>,
+    peer_ip: Option<IpAddr>,
+    over_ip_limit: bool,
+    // Session channels that may still turn into an SFTP subsystem.
+    channels: HashMap<ChannelId, Channel<Msg>>,
+    sftp_channels: HashSet<ChannelId>,
+    // The login name picks which scene the session opens on.
+    user: String,
+    // SHA256 fingerprint of the key this connection authenticated with.
+    fingerprint: Option<String>,
+    caps: ClientCaps,
+    // The client's version string, known once it authenticates. Also marks the
+    // connection as audited, so its disconnect gets logged too.
+    client_version: Option<String>,
+    connected_at: std::time::Instant,
+    bytes_sent: Arc<AtomicU64>,
+    bytes_received: u64,
+}
+
+impl AppServer {
+    pub fn new(cli: Cli) -> Self {
+        Self {
+            cli: Arc::new(cli),
+            next_id: 0,
+        }
+    }
+    // ROBERT_HOST_KEY, when set, takes precedence over anything on disk.
+    // SECRETS_LOCATION is a comma-separated list of key files and/or directories of key files.
+    fn load_host_keys() -> Result<Vec<russh::keys::PrivateKey>, anyhow::Error> {
+        if let Ok(encoded) = env::var("ROBERT_HOST_KEY") {
+            let key = Self::decode_host_key(&encoded)?;
+            println!("Loaded {} host key from ROBERT_HOST_KEY", key.algorithm());
+            return Ok(vec![key]);
+        }
+
+        let secrets_location =
+            env::var("SECRETS_LOCATION").expect("SECRETS_LOCATION was not defined.");
+
+        let mut keys = Vec::new();
+        for location in secrets_location.split(',').map(str::trim) {
+            let key_path = Path::new(location);
+
+            if key_path.is_dir() {
+                let mut dir_keys = Self::load_host_key_dir(key_path)?;
+                if dir_keys.is_empty() {
+                    dir_keys.push(Self::generate_host_key(&key_path.join("id_ed25519"))?);
+                }
+                keys.append(&mut dir_keys);
+            } else if key_path.exists() {
+                let key = russh::keys::PrivateKey::read_openssh_file(key_path).map_err(|e| {
+                    anyhow::anyhow!("Failed to read host key {}: {}", key_path.display(), e)
+                })?;
+                keys.push(key);
+            } else {
+                keys.push(Self::generate_host_key(key_path)?);
+            }
+        }
+
+        for key in &keys {
+            println!("Loaded {} host key", key.algorithm());
+        }
+
+        Ok(keys)
+    }
+
+    // Accepts either the base64 of an OpenSSH private key file or the PEM text itself.
+    fn decode_host_key(encoded: &str) -> Result<russh::keys::PrivateKey, anyhow::Error> {
+        let encoded = encoded.trim();
+        let pem = if encoded.starts_with("-----BEGIN") {
+            encoded.as_bytes().to_vec()
+        } else {
+            BASE64_STANDARD
+                .decode(encoded)
+                .map_err(|e| anyhow::anyhow!("ROBERT_HOST_KEY is not valid base64: {}", e))?
+        };
+
+        russh::keys::PrivateKey::from_openssh(pem)
+            .map_err(|e| anyhow::anyhow!("Failed to parse ROBERT_HOST_KEY: {}", e))
+    }
+
+    fn load_host_key_dir(dir: &Path) -> Result<Vec<russh::keys::PrivateKey>, anyhow::Error> {
+        let mut paths = std::fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.is_file() && path.extension().is_none_or(|ext| ext != "pub"))
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        let mut keys = Vec::new();
+        for path in paths {
+            match russh::keys::PrivateKey::read_openssh_file(&path) {
+                Ok(key) => keys.push(key),
+                Err(e) => eprintln!("Skipping {}: {}", path.display(), e),
+            }
+        }
+
+        Ok(keys)
+    }
+
+    fn generate_host_key(key_path: &Path) -> Result<russh::keys::PrivateKey, anyhow::Error> {
+        println!(
+            "Host key not found at {}, generating a new ed25519 key",
+            key_path.display()
+        );
+
+        let key = russh::keys::PrivateKey::random(&mut OsRng, Algorithm::Ed25519)
+            .map_err(|e| anyhow::anyhow!("Failed to generate host key: {}", e))?;
+
+        if let Some(parent) = key_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        // Written with 0600 permissions on unix, like ssh-keygen does.
+        key.write_openssh_file(key_path, LineEnding::LF)
+            .map_err(|e| anyhow::anyhow!("Failed to write host key: {}", e))?;
+
+        Ok(key)
+    }
+
+    fn load_config_file(&self) -> Result<ConfigFile, anyhow::Error> {
+        let Some(path) = &self.cli.config else {
+            return Ok(ConfigFile::default());
+        };
+
+        let config_file = ConfigFile::load(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read config at {}: {}", path.display(), e))?;
+        println!("Loaded config from {}", path.display());
+        Ok(config_file)
+    }
+
+    fn load_authorized_keys() -> Result<Option<Vec<PublicKey>>, anyhow::Error> {
+        let Ok(keys_location) = env::var("AUTHORIZED_KEYS_LOCATION") else {
+            return Ok(None);
+        };
+
+        let entries = AuthorizedKeys::read_file(&keys_location).map_err(|e| {
+            anyhow::anyhow!("Failed to read authorized keys at {}: {}", keys_location, e)
+        })?;
+
+        let keys = entries
+            .into_iter()
+            .map(|entry| entry.public_key().clone())
+            .collect();
+
+        Ok(Some(keys))
+    }
+
+    // CA public keys, in the same format as authorized_keys, like sshd's TrustedUserCAKeys.
+    fn load_trusted_cas() -> Result<Option<Vec<Fingerprint>>, anyhow::Error> {
+        let Ok(cas_location) = env::var("TRUSTED_USER_CA_KEYS_LOCATION") else {
+            return Ok(None);
+        };
+
+        let entries = AuthorizedKeys::read_file(&cas_location).map_err(|e| {
+            anyhow::anyhow!("Failed to read trusted CA keys at {}: {}", cas_location, e)
+        })?;
+
+        let fingerprints = entries
+            .into_iter()
+            .map(|entry| entry.public_key().fingerprint(HashAlg::Sha256))
+            .collect();
+
+        Ok(Some(fingerprints))
+    }
+
+    // One `user:$argon2id$...` entry per line; blank lines and `#` comments are skipped.
+    fn load_credentials() -> Result<Option<HashMap<String, String>>, anyhow::Error> {
+        let Ok(credentials_location) = env::var("CREDENTIALS_LOCATION") else {
+            return Ok(None);
+        };
+
+        let contents = std::fs::read_to_string(&credentials_location).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to read credentials at {}: {}",
+                credentials_location,
+                e
+            )
+        })?;
+
+        let mut credentials = HashMap::new();
+        for (line_number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (user, hash) = line.split_once(':').ok_or_else(|| {
+                anyhow::anyhow!("Malformed credentials entry on line {}", line_number + 1)
+            })?;
+            PasswordHash::new(hash).map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid hash for {} on line {}: {}",
+                    user,
+                    line_number + 1,
+                    e
+                )
+            })?;
+
+            credentials.insert(user.to_string(), hash.to_string());
+        }
+
+        Ok(Some(credentials))
+    }
+
+    fn load_profiles() -> Result<Option<ProfileStore>, anyhow::Error> {
+        let Ok(profiles_location) = env::var("PROFILES_LOCATION") else {
+            return Ok(None);
+        };
+
+        let profiles = ProfileStore::load(Path::new(&profiles_location)).map_err(|e| {
+            anyhow::anyhow!("Failed to read profiles at {}: {}", profiles_location, e)
+        })?;
+        Ok(Some(profiles))
+    }
+
+    fn load_corner_counter() -> Result<CornerCounter, anyhow::Error> {
+        let Ok(corner_hits_location) = env::var("CORNER_HITS_LOCATION") else {
+            return Ok(CornerCounter::in_memory());
+        };
+
+        let counter = CornerCounter::load(Path::new(&corner_hits_location)).map_err(|e| {
+            anyhow::anyhow!(
+                "Failed to read corner hits at {}: {}",
+                corner_hits_location,
+                e
+            )
+        })?;
+        println!(
+            "Corner hits saved to {corner_hits_location} ({} so far)",
+            counter.total()
+        );
+        Ok(counter)
+    }
+
+    fn load_audit_log() -> Result<Option<AuditLog>, anyhow::Error> {
+        let Ok(audit_log_location) = env::var("AUDIT_LOG_LOCATION") else {
+            return Ok(None);
+        };
+
+        let audit_log = AuditLog::open(Path::new(&audit_log_location)).map_err(|e| {
+            anyhow::anyhow!("Failed to open audit log at {}: {}", audit_log_location, e)
+        })?;
+        println!("Audit log enabled at {audit_log_location}");
+        Ok(Some(audit_log))
+    }
+
+    pub async fn run(&mut self) -> Result<(), anyhow::Error> {
+        let config_file = self.load_config_file()?;
+
+        let authorized_keys = Self::load_authorized_keys()
+            .map_err(|e| anyhow::anyhow!("Failed to load authorized keys: {}", e))?;
+
+        let trusted_cas = Self::load_trusted_cas()
+            .map_err(|e| anyhow::anyhow!("Failed to load trusted CA keys: {}", e))?;
+
+        let credentials = Self::load_credentials()
+            .map_err(|e| anyhow::anyhow!("Failed to load credentials: {}", e))?;
+
+        let audit_log = Self::load_audit_log()?;
+
+        let corner_counter = Arc::new(Self::load_corner_counter()?);
+
+        let profiles = Self::load_profiles()
+            .map_err(|e| anyhow::anyhow!("Failed to load profiles: {}", e))?
+            .map(|profiles| {
+                println!("Profiles enabled ({} saved)", profiles.len());
+                Arc::new(std::sync::Mutex::new(profiles))
+            });
+
+        let state = Arc::new(ServerState {
+            cli: self.cli.clone(),
+            clients: Mutex::new(HashMap::new()),
+            authorized_keys,
+            trusted_cas,
+            credentials,
+            connections_per_ip: std::sync::Mutex::new(HashMap::new()),
+            firewall: Firewall::new(self.cli.allow.clone(), self.cli.deny.clone()),
+            bans: std::sync::Mutex::new(BanTracker::new(
+                self.cli.ban_connections_per_minute,
+                self.cli.ban_auth_failures,
+                std::time::Duration::from_secs(self.cli.ban_duration_secs),
+            )),
+            started_at: std::time::Instant::now(),
+            uploads: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            default_sprites: load_sprites(&self.cli),
+            profiles,
+            registry: SessionRegistry::default(),
+            drain: Notify::new(),
+            config_file,
+            audit_log,
+            corner_counter,
+            waiting: std::sync::Mutex::new(VecDeque::new()),
+        });
+
+        let ban_state = state.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(tokio::time::Duration::from_secs(10)).await;
+                ban_state.bans.lock().unwrap().expire();
+            }
+        });
+
+        let mut methods = MethodSet::empty();
+        if let Some(keys) = &state.authorized_keys {
+            println!("Public key authentication enabled ({} keys)", keys.len());
+        }
+        if let Some(cas) = &state.trusted_cas {
+            println!("Certificate authentication enabled ({} CAs)", cas.len());
+        }
+        if state.authorized_keys.is_some() || state.trusted_cas.is_some() {
+            methods.push(MethodKind::PublicKey);
+        }
+        if let Some(credentials) = &state.credentials {
+            println!(
+                "Password authentication enabled ({} users)",
+                credentials.len()
+            );
+            methods.push(MethodKind::Password);
+        }
+        if state.is_anonymous() {
+            methods.push(MethodKind::None);
+        }
+
+        let host_keys = Self::load_host_keys()
+            .map_err(|e| anyhow::anyhow!("Failed to load host keys: {}", e))?;
+
+        let preferred = state
+            .config_file
+            .algorithms
+            .preferred()
+            .map_err(|e| anyhow::anyhow!("Invalid algorithm settings: {}", e))?;
+        // RSA keys can sign with any of the rsa-sha2 variants; everything else must match.
+        let usable_host_key = host_keys.iter().any(|key| {
+            preferred.key.iter().any(|algorithm| {
+                key.algorithm() == *algorithm
+                    || (key.algorithm().is_rsa() && algorithm.clone().is_rsa())
+            })
+        });
+        if !usable_host_key {
+            return Err(anyhow::anyhow!(
+                "None of the loaded host keys match the allowed host key algorithms"
+            ));
+        }
+
+        let config = Config {
+            inactivity_timeout: state.config_file.connection.inactivity_timeout(),
+            auth_rejection_time: state.config_file.connection.auth_rejection_time(),
+            auth_rejection_time_initial: Some(std::time::Duration::from_secs(0)),
+            methods,
+            keys: host_keys,
+            nodelay: state.config_file.connection.nodelay,
+            keepalive_interval: (self.cli.keepalive_secs > 0)
+                .then(|| std::time::Duration::from_secs(self.cli.keepalive_secs)),
+            keepalive_max: self.cli.keepalive_max,
+            preferred,
+            ..Default::default()
+        };
+
+        let address = (self.cli.bind, self.cli.port);
+        let socket = TcpListener::bind(address).await?;
+        println!("Starting server on {}:{}", address.0, address.1);
+
+        let bind = self.cli.bind;
+        let listeners = Listeners {
+            telnet: listen(bind, self.cli.telnet_port, "telnet").await?,
+            ansi: listen(bind, self.cli.ansi_port, "plain ANSI").await?,
+            web: listen(bind, self.cli.web_port, "the web terminal").await?,
+        };
+        self.accept_loop(state, Arc::new(config), socket, listeners)
+            .await
+    }
+
+    // Our own take on `Server::run_on_socket`, so peers can be turned away before a
+    // handler exists for them.
+    async fn accept_loop(
+        &mut self,
+        state: Arc<ServerState>,
+        config: Arc<Config>,
+        socket: TcpListener,
+        mut listeners: Listeners,
+    ) -> Result<(), anyhow::Error> {
+        let (shutdown_tx, _) = broadcast::channel::<String>(1);
+        let mut sessions = JoinSet::new();
+        let (accepted_tx, mut accepted_rx) = unbounded_channel::<(TcpStream, SocketAddr)>();
+        let mut accept_limiter = self
+            .cli
+            .accept_rate
+            .map(|rate| TokenBucket::new(rate, self.cli.accept_burst.max(1.0)));
+        let shutdown = shutdown_signal();
+        tokio::pin!(shutdown);
+        let drain = drain_signal(&state.drain);
+        tokio::pin!(drain);
+        // Dropped when draining starts, so new connections are refused outright.
+        let mut socket = Some(socket);
+
+        loop {
+            tokio::select! {
+                accept_result = accept(&socket) => {
+                    let (mut stream, peer_addr) = match accept_result {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            eprintln!("Failed to accept connection: {e}");
+                            continue;
+                        }
+                    };
+
+                    if !self.cli.proxy_protocol {
+                        let _ = accepted_tx.send((stream, peer_addr));
+                        continue;
+                    }
+
+                    // The header is read off the accept path so a slow peer can't stall it.
+                    let accepted_tx = accepted_tx.clone();
+                    tokio::spawn(async move {
+                        let header = tokio::time::timeout(
+                            std::time::Duration::from_secs(5),
+                            proxy_protocol::read_header(&mut stream),
+                        )
+                        .await;
+                        match header {
+                            Ok(Ok(client_addr)) => {
+                                let _ = accepted_tx.send((stream, client_addr.unwrap_or(peer_addr)));
+                            }
+                            Ok(Err(e)) => eprintln!("Bad PROXY header from {peer_addr}: {e}"),
+                            Err(_) => eprintln!("Timed out reading PROXY header from {peer_addr}"),
+                        }
+                    });
+                }
+
+                Some((stream, peer_addr)) = accepted_rx.recv() => {
+                    // Connections still finishing their PROXY header when draining began.
+                    if socket.is_none() {
+                        continue;
+                    }
+
+                    if !admit(&state, &mut accept_limiter, peer_addr) {
+                        continue;
+                    }
+
+                    if let Err(e) = state.config_file.connection.apply(&stream) {
+                        eprintln!("Failed to set socket options: {e}");
+                    }
+                    let handler = self.new_connection(&state, peer_addr);
+                    let config = config.clone();
+                    let mut shutdown_rx = shutdown_tx.subscribe();
+
+                    sessions.spawn(async move {
+                        let session = match run_stream(config, stream, handler).await {
+                            Ok(session) => session,
+                            Err(e) => {
+                                eprintln!("Connection setup failed: {e}");
+                                return;
+                            }
+                        };
+                        let handle = session.handle();
+
+                        tokio::select! {
+                            reason = shutdown_rx.recv() => {
+                                let _ = handle
+                                    .disconnect(
+                                        Disconnect::ByApplication,
+                                        reason.unwrap_or_default(),
+                                        String::new(),
+                                    )
+                                    .await;
+                            }
+                            result = session => {
+                                if let Err(e) = result {
+                                    eprintln!("Session closed with error: {e}");
+                                }
+                            }
+                        }
+                    });
+                }
+
+                (transport, accept_result) = listeners.accept() => {
+                    let (stream, peer_addr) = match accept_result {
+                        Ok(accepted) => accepted,
+                        Err(e) => {
+                            eprintln!("Failed to accept {transport:?} connection: {e}");
+                            continue;
+                        }
+                    };
+                    if !admit(&state, &mut accept_limiter, peer_addr) {
+                        continue;
+                    }
+
+                    if let Err(e) = state.config_file.connection.apply(&stream) {
+                        eprintln!("Failed to set socket options: {e}");
+                    }
+                    let handler = self.new_connection(&state, peer_addr);
+                    let mut shutdown_rx = shutdown_tx.subscribe();
+                    sessions.spawn(async move {
+                        tokio::select! {
+                            _ = shutdown_rx.recv() => {}
+                            _ = handler.serve(transport, stream) => {}
+                        }
+                    });
+                }
+
+                // Reap finished sessions so the set doesn't grow forever.
+                Some(_) = sessions.join_next() => {
+                    if socket.is_none() && sessions.is_empty() {
+                        println!("Drained, exiting");
+                        return Ok(());
+                    }
+                }
+
+                _ = &mut drain, if socket.is_some() => {
+                    socket = None;
+                    listeners = Listeners::default();
+                    println!(
+                        "Draining: no longer accepting connections, waiting on {} sessions",
+                        sessions.len()
+                    );
+                    if sessions.is_empty() {
+                        println!("Drained, exiting");
+                        return Ok(());
+                    }
+                }
+
+                _ = &mut shutdown => {
+                    // Drain before closing so no session handler blocks on the lock meanwhile.
+                    let drained: Vec<SharedClient> =
+                        state.clients.lock().await.drain().map(|(_, c)| c).collect();
+                    println!("Shutting down, resetting {} clients", drained.len());
+                    for client in drained {
+                        client.lock().await.close().await;
+                    }
+
+                    let _ = shutdown_tx.send("Server shutting down".to_string());
+                    let _ = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                        while sessions.join_next().await.is_some() {}
+                    })
+                    .await;
+
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // Gives an accepted connection its own id and handler.
+    fn new_connection(
+        &mut self,
+        state: &Arc<ServerState>,
+        peer_addr: SocketAddr,
+    ) -> ConnectionHandler {
+        let id = self.next_id;
+        self.next_id += 1;
+        ConnectionHandler::new(id, state.clone(), Some(peer_addr))
+    }
+}
+
+// Draws one session at its own frame rate until it ends. Only a weak reference is kept,
+// so the task also stops once the session is gone from the map.
+async fn render_session(state: Arc<ServerState>, id: usize, client: Weak<Mutex<Client>>) {
+    let mut period = std::time::Duration::from_secs(1) / state.cli.fps;
+    let mut interval = frame_interval(period);
+    // Things move at TICK_RATE however slowly the session is drawn, so a throttled
+    // client sees fewer frames rather than a slower robert.
+    let mut ticks = frame_interval(std::time::Duration::from_secs(1) / TICK_RATE);
+    let mut updated_at = std::time::Instant::now();
+    loop {
+        let drawing = tokio::select! {
+            _ = interval.tick() => true,
+            _ = ticks.tick() => false,
+        };
+        let Some(client) = client.upgrade() else {
+            return;
+        };
+
+        if !drawing {
+            let mut guard = client.lock().await;
+            // Frozen while the window is too large to draw him in.
+            if !guard.too_large {
+                guard.app.update(updated_at.elapsed());
+            }
+            updated_at = std::time::Instant::now();
+            continue;
+        }
+
+        let mut waiting = state.queue_position(id);
+        let promoted = waiting.is_some() && state.promote(id).await;
+        if promoted {
+            waiting = None;
+        }
+
+        let mut guard = client.lock().await;
+        if promoted {
+            println!("Letting session {id} in from the waiting room");
+            // Time spent waiting doesn't count against the session.
+            guard.app.last_input = std::time::Instant::now();
+            guard.app.started_at = std::time::Instant::now();
+        }
+        if guard.is_finished(waiting.is_some()) {
+            drop(guard);
+            state.end_session(id, &client).await;
+            return;
+        }
+
+        // Picked up on the next tick after `f` or the client's link changes it.
+        guard.adapt_frame_rate();
+        if guard.app.frame_interval() != period {
+            period = guard.app.frame_interval();
+            interval = frame_interval(period);
+        }
+
+        if let Some(frame) = guard.json_frame {
+            // Nothing goes out until it's through the waiting room.
+            if waiting.is_none() {
+                guard.json_frame = Some(frame + 1);
+                let line = guard.app.state_json(frame);
+                let drawing_at = std::time::Instant::now();
+                let backend = guard.terminal.backend_mut();
+                match backend
+                    .write_all(line.as_bytes())
+                    .and_then(|()| backend.flush())
+                {
+                    Ok(()) => guard.stats.record_frame(drawing_at.elapsed()),
+                    Err(e) => eprintln!("Failed to stream session {id}: {e}"),
+                }
+            }
+            continue;
+        }
+
+        if let Err(e) = guard.settle_resize() {
+            eprintln!("Failed to resize session {id}: {e}");
+        }
+
+        // Makes ratatui forget the last frame, so the next one is drawn whole.
+        if guard.frames.take_needs_redraw() {
+            guard.terminal.swap_buffers();
+            guard.last_frame = Buffer::default();
+        }
+
+        let broadcast = state.registry.current_broadcast();
+        let Client {
+            terminal,
+            app,
+            stats,
+            too_large,
+            frames,
+            last_frame,
+            ..
+        } = &mut *guard;
+        let mut image = None;
+        let drawing_at = std::time::Instant::now();
+        let drawn = terminal.draw(|f| {
+            if let Some((position, count)) = waiting {
+                app.image_at = None;
+                draw_waiting_room(f, position, count);
+            } else if *too_large {
+                app.image_at = None;
+                draw_too_large(f, state.cli.max_cols, state.cli.max_rows);
+            } else {
+                app.render(f);
+            }
+            if let Some(message) = &broadcast {
+                draw_broadcast(f, message);
+                // An image would cover the notice, so robert sits this one out.
+                if app.image_at.take().is_some() {
+                    let area = f.area();
+                    for position in area.positions() {
+                        f.buffer_mut()[position].set_skip(false);
+                    }
+                }
+            }
+            // Ratatui would still send its style resets and cursor hiding.
+            if f.buffer_mut() == last_frame {
+                frames.skip_next();
+            } else {
+                last_frame.clone_from(f.buffer_mut());
+                image = app.image_frame();
+            }
+        });
+        // Rung after the frame, along with the image if there is one.
+        if app.take_bell() {
+            image.get_or_insert_default().push(BEL);
+        }
+        if let Some(on) = app.take_mouse_switch() {
+            let switch = if on { MOUSE_ON } else { MOUSE_OFF };
+            image.get_or_insert_default().extend_from_slice(switch);
+        }
+        // Written straight after the frame, into the cells it left alone.
+        let drawn = drawn.map(|_| ()).and_then(|()| match image {
+            Some(image) => {
+                let backend = terminal.backend_mut();
+                backend.write_all(&image)?;
+                backend.flush()
+            }
+            None => Ok(()),
+        });
+        match drawn {
+            Ok(()) => stats.record_frame(drawing_at.elapsed()),
+            // Picked up and evicted on the next tick.
+            Err(e) => eprintln!("Failed to draw session {id}: {e}"),
+        }
+    }
+}
+
+// The file robert's sprite for `mood` is loaded from, if it has one of its own.
+fn sprite_path(cli: &Cli, mood: Mood) -> Option<&str> {
+    match mood {
+        Mood::Calm => Some(&cli.normal),
+        Mood::Scared => Some(&cli.scared),
+        Mood::Dizzy => cli.dizzy.as_deref(),
+        Mood::Sleepy => cli.sleepy.as_deref(),
+        Mood::Excited => cli.excited.as_deref(),
+    }
+}
+
+// Every mood's sprite, from its file or drawn from the calm one.
+fn load_sprites(cli: &Cli) -> MoodSprites {
+    let calm = load_to_pixel_map(&cli.normal, cli.chroma_key);
+    Mood::ALL.map(|mood| {
+        Arc::new(match sprite_path(cli, mood) {
+            Some(path) => load_to_pixel_map(path, cli.chroma_key),
+            None => to_pixel_map(&mood_sprite(&calm.image, mood)),
+        })
+    })
+}
+
+fn frame_interval(period: std::time::Duration) -> tokio::time::Interval {
+    let mut interval = tokio::time::interval(period);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    interval
+}
+
+// Firewall, bans and the accept rate, in that order. False if the peer is turned away.
+fn admit(
+    state: &ServerState,
+    accept_limiter: &mut Option<TokenBucket>,
+    peer_addr: SocketAddr,
+) -> bool {
+    if !state.firewall.allows(peer_addr.ip()) {
+        println!(
+            "Rejected connection from {}: blocked by firewall",
+            peer_addr.ip()
+        );
+        return false;
+    }
+
+    {
+        let mut bans = state.bans.lock().unwrap();
+        bans.record_connection(peer_addr.ip());
+        if bans.is_banned(peer_addr.ip()) {
+            return false;
+        }
+    }
+
+    if let Some(limiter) = accept_limiter
+        && !limiter.try_take()
+    {
+        println!(
+            "Dropped connection from {}: accept rate exceeded",
+            peer_addr.ip()
+        );
+        return false;
+    }
+
+    true
+}
+
+// Binds one of the optional listeners, if its port was given.
+async fn listen(
+    bind: IpAddr,
+    port: Option<u16>,
+    what: &str,
+) -> Result<Option<TcpListener>, anyhow::Error> {
+    let Some(port) = port else {
+        return Ok(None);
+    };
+    let socket = TcpListener::bind((bind, port)).await?;
+    println!("Serving {what} on {bind}:{port}");
+    Ok(Some(socket))
+}
+
+async fn accept(socket: &Option<TcpListener>) -> std::io::Result<(TcpStream, SocketAddr)> {
+    match socket {
+        Some(socket) => socket.accept().await,
+        None => std::future::pending().await,
+    }
+}
+
+// SIGHUP or the admin `drain` command.
+async fn drain_signal(drain: &Notify) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut sighup = signal(SignalKind::hangup()).expect("Failed to install SIGHUP handler");
+        tokio::select! {
+            _ = sighup.recv() => {}
+            _ = drain.notified() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        drain.notified().await;
+    }
+}
+
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        let mut sigterm =
+            signal(SignalKind::terminate()).expect("Failed to install SIGTERM handler");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = sigterm.recv() => {}
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
 }
 
-impl TerminalHandle {
-    async fn start(handle: Handle, channel_id: ChannelId) -> Self {
-        let (sender, mut receiver) = unbounded_channel::<Vec<u8>>();
-        tokio::spawn(async move {
-            while let Some(data) = receiver.recv().await {
-                let result = handle.data(channel_id, data.into()).await;
-                if result.is_err() {
-                    eprintln!("Failed to send data: {result:?}");
-                }
+impl ServerState {
+    async fn is_full(&self) -> bool {
+        let Some(max_sessions) = self.cli.max_sessions else {
+            return false;
+        };
+        let clients = self.clients.lock().await;
+        let mut waiting = self.waiting.lock().unwrap();
+        // Sessions that ended while waiting don't count as waiting.
+        waiting.retain(|id| clients.contains_key(id));
+        clients.len().saturating_sub(waiting.len()) >= max_sessions
+    }
+
+    // Puts session `id` in the map, and at the back of the waiting room if every slot is
+    // taken or others are already in line. Done under the clients lock `promote` takes
+    // too, so two sessions starting together can't both have the last slot. True if it
+    // has to wait.
+    async fn add_client(&self, id: usize, client: SharedClient) -> bool {
+        let mut clients = self.clients.lock().await;
+        let mut waiting = self.waiting.lock().unwrap();
+        // Sessions that ended while waiting don't count as waiting.
+        waiting.retain(|id| clients.contains_key(id));
+        let playing = clients.len().saturating_sub(waiting.len());
+        let wait = !waiting.is_empty() || self.cli.max_sessions.is_some_and(|max| playing >= max);
+        clients.insert(id, client);
+        if wait {
+            waiting.push_back(id);
+        }
+        wait
+    }
+
+    // How many sessions are playing, and how many are in the waiting room.
+    async fn session_counts(&self) -> (usize, usize) {
+        let clients = self.clients.lock().await;
+        let mut waiting = self.waiting.lock().unwrap();
+        waiting.retain(|id| clients.contains_key(id));
+        (clients.len().saturating_sub(waiting.len()), waiting.len())
+    }
+
+    // Where session `id` is in the waiting room, and how many are waiting, if it's there.
+    fn queue_position(&self, id: usize) -> Option<(usize, usize)> {
+        let waiting = self.waiting.lock().unwrap();
+        let index = waiting.iter().position(|waiting_id| *waiting_id == id)?;
+        Some((index + 1, waiting.len()))
+    }
+
+    // Lets the front of the waiting room in if there's a slot free. True if that was `id`.
+    async fn promote(&self, id: usize) -> bool {
+        let clients = self.clients.lock().await;
+        let mut waiting = self.waiting.lock().unwrap();
+        // Sessions that ended while waiting don't hold up the line.
+        waiting.retain(|id| clients.contains_key(id));
+
+        let max_sessions = self.cli.max_sessions.unwrap_or(usize::MAX);
+        let playing = clients.len().saturating_sub(waiting.len());
+        if waiting.front() != Some(&id) || playing >= max_sessions {
+            return false;
+        }
+        waiting.pop_front();
+        true
+    }
+
+    // Takes session `id` out of the map, and out of the waiting room along with it so
+    // the two agree on who's waiting.
+    async fn remove_client(&self, id: usize) -> Option<SharedClient> {
+        let client = self.clients.lock().await.remove(&id);
+        self.leave_waiting_room(id);
+        client
+    }
+
+    fn leave_waiting_room(&self, id: usize) {
+        self.waiting
+            .lock()
+            .unwrap()
+            .retain(|waiting_id| *waiting_id != id);
+    }
+
+    fn is_anonymous(&self) -> bool {
+        self.authorized_keys.is_none() && self.trusted_cas.is_none() && self.credentials.is_none()
+    }
+
+    fn verify_password(&self, user: &str, password: &str) -> bool {
+        let Some(hash) = self
+            .credentials
+            .as_ref()
+            .and_then(|credentials| credentials.get(user))
+        else {
+            return false;
+        };
+
+        match PasswordHash::new(hash) {
+            Ok(parsed) => Argon2::default()
+                .verify_password(password.as_bytes(), &parsed)
+                .is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    fn is_authorized(&self, public_key: &PublicKey) -> bool {
+        match &self.authorized_keys {
+            Some(keys) => keys
+                .iter()
+                .any(|key| key.key_data() == public_key.key_data()),
+            None => false,
+        }
+    }
+
+    // A user certificate signed by one of our CAs, in date, naming `user` as a principal.
+    fn is_trusted_certificate(&self, user: &str, certificate: &Certificate) -> bool {
+        let Some(cas) = &self.trusted_cas else {
+            return false;
+        };
+        certificate.cert_type() == CertType::User
+            && certificate
+                .valid_principals()
+                .iter()
+                .any(|principal| principal == user)
+            && certificate.validate(cas).is_ok()
+    }
+
+    // Robert's sprite in every mood for an uploader, preferring anything it uploaded.
+    fn sprites(&self, uploader: &Uploader) -> MoodSprites {
+        let uploaded = self.uploads.lock().unwrap().get(uploader).cloned();
+        let load = |mood: Mood| {
+            uploaded
+                .as_ref()
+                .and_then(|files| files.get(mood.sprite_file()))
+                .and_then(|data| pixel_map_from_png(data, self.cli.chroma_key).ok())
+        };
+        let calm = load(Mood::Calm);
+        Mood::ALL.map(|mood| match (load(mood), &calm) {
+            (Some(uploaded), _) => Arc::new(uploaded),
+            // Moods drawn from the calm sprite are drawn from an uploaded one too.
+            (None, Some(calm)) if sprite_path(&self.cli, mood).is_none() => {
+                Arc::new(to_pixel_map(&mood_sprite(&calm.image, mood)))
+            }
+            _ => self.default_sprites[mood.index()].clone(),
+        })
+    }
+
+    // Ends session `id`, taking it out of the map under the same lock it was found with.
+    // False if there's no such session. The only way a session is kicked: both the `kick ID`
+    // command and the admin dashboard come through here.
+    pub async fn kick(&self, id: usize) -> bool {
+        let Some(client) = self.remove_client(id).await else {
+            return false;
+        };
+        println!("Kicking session {id}");
+        client.lock().await.terminate().await;
+        true
+    }
+
+    // Takes a session that's run its course out of the map and closes it, unless
+    // something else (a kick, a quit) got to it first.
+    async fn end_session(&self, id: usize, client: &SharedClient) {
+        {
+            let mut clients = self.clients.lock().await;
+            if !clients
+                .get(&id)
+                .is_some_and(|current| Arc::ptr_eq(current, client))
+            {
+                return;
             }
-        });
-        Self {
-            sender,
-            sink: Vec::new(),
+            clients.remove(&id);
+            self.leave_waiting_room(id);
+        }
+
+        let mut client = client.lock().await;
+        if !client.is_alive() {
+            println!("Evicting unresponsive session {id}");
+            client.close().await;
+        } else if client.app.is_ttl_expired() {
+            println!("Session {id} reached its time limit");
+            let _ = client.terminal.draw(draw_goodbye);
+            tokio::time::sleep(tokio::time::Duration::from_secs(3)).await;
+            client.close().await;
+        } else {
+            println!("Closing idle session {id}");
+            client.close().await;
         }
     }
 }
 
-// The crossterm backend writes to the terminal handle.
-impl std::io::Write for TerminalHandle {
-    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        self.sink.extend_from_slice(buf);
-        Ok(buf.len())
+impl ConnectionHandler {
+    fn new(id: usize, state: Arc<ServerState>, peer_addr: Option<SocketAddr>) -> Self {
+        let peer_ip = peer_addr.map(|addr| addr.ip());
+        let mut over_ip_limit = false;
+        if let Some(ip) = peer_ip {
+            let mut connections = state.connections_per_ip.lock().unwrap();
+            let count = connections.entry(ip).or_insert(0);
+            *count += 1;
+            over_ip_limit = state.cli.max_per_ip.is_some_and(|max| *count > max);
+        }
+
+        let handler = Self {
+            id,
+            state,
+            pty_requested: false,
+            terminal_channel: None,
+            peer_addr,
+            peer_ip,
+            over_ip_limit,
+            channels: HashMap::new(),
+            sftp_channels: HashSet::new(),
+            user: String::new(),
+            fingerprint: None,
+            caps: ClientCaps::default(),
+            client_version: None,
+            connected_at: std::time::Instant::now(),
+            bytes_sent: Arc::new(AtomicU64::new(0)),
+            bytes_received: 0,
+        };
+        handler.audit(AuditEvent::new("connect", id));
+        handler
     }
 
-    fn flush(&mut self) -> std::io::Result<()> {
-        let result = self.sender.send(self.sink.clone());
-        if result.is_err() {
-            return Err(std::io::Error::new(
-                std::io::ErrorKind::BrokenPipe,
-                result.unwrap_err(),
-            ));
+    // Stamps `event` with who this connection is and appends it to the audit log.
+    fn audit(&self, mut event: AuditEvent) {
+        let Some(audit_log) = &self.state.audit_log else {
+            return;
+        };
+        event.peer = self.peer_addr.map(|addr| addr.to_string());
+        event.user = (!self.user.is_empty()).then(|| self.user.clone());
+        event.client = self.client_version.clone();
+        audit_log.record(&event);
+    }
+
+    // Uploads follow the key this connection signed in with, or stay with the connection.
+    fn uploader(&self) -> Uploader {
+        match &self.fingerprint {
+            Some(fingerprint) => Uploader::Key(fingerprint.clone()),
+            None => Uploader::Connection(self.id),
+        }
+    }
+
+    fn profile(&self) -> Option<Profile> {
+        let fingerprint = self.fingerprint.as_ref()?;
+        self.state
+            .profiles
+            .as_ref()?
+            .lock()
+            .unwrap()
+            .get(fingerprint)
+    }
+
+    // The dashboard is only for the configured admin, and only over public key auth.
+    fn is_admin(&self) -> bool {
+        self.fingerprint.is_some()
+            && self.state.cli.admin_user.as_deref() == Some(self.user.as_str())
+    }
+
+    fn new_app(&self) -> App {
+        let pixel_maps = self.state.sprites(&self.uploader());
+        let profile = self.profile();
+        // A scene picked by login name wins over the one saved last time.
+        let scene = if self.is_admin() {
+            Scene::Admin
+        } else {
+            Scene::from_name(&self.user)
+                .or(profile.map(|profile| profile.scene))
+                .unwrap_or(Scene::Splash)
+        };
+        let reduced_motion = self.state.config_file.reduced_motion;
+        // The speed setting saved last time, on top of any slowing down for reduced motion.
+        let speed_setting = profile.and_then(|profile| profile.speed).unwrap_or(1.0);
+        let speed = if reduced_motion {
+            REDUCED_MOTION_SPEED * speed_setting
+        } else {
+            speed_setting
+        };
+        let mut rng = StdRng::from_entropy();
+        let quips = self
+            .state
+            .config_file
+            .quips
+            .clone()
+            .map(|config| Quips::new(config, &mut rng));
+        // The first robert starts where he always has; the rest are spread out, heading
+        // any which way.
+        let physics = self.state.config_file.physics;
+        let (cruise_x, cruise_y) = (physics.cruise_x * speed, physics.cruise_y * speed);
+        let mut sprites = vec![Sprite::new((0.0, 0.0), -cruise_x, -cruise_y)];
+        for i in 1..self.state.cli.roberts {
+            let mut heading = || if rng.gen_bool(0.5) { 1.0 } else { -1.0 };
+            let (sx, sy) = (heading() * cruise_x, heading() * cruise_y);
+            sprites.push(Sprite::new(Sprite::start_offset(i as usize), sx, sy));
+        }
+        App {
+            sprites,
+            pixel_maps,
+            rng,
+            scene,
+            idle_timeout: self
+                .state
+                .cli
+                .idle_timeout_secs
+                .map(std::time::Duration::from_secs),
+            last_input: std::time::Instant::now(),
+            session_ttl: self
+                .state
+                .cli
+                .session_ttl_secs
+                .map(std::time::Duration::from_secs),
+            started_at: std::time::Instant::now(),
+            matrix: MatrixRain::default(),
+            pong: Pong::new(profile.map_or(0, |profile| profile.high_score)),
+            flock: Flock::default(),
+            follow_mouse: false,
+            mouse_pending: false,
+            pointer: None,
+            speed: speed_setting,
+            speed_changed: None,
+            paused: false,
+            admin: self.is_admin().then(|| {
+                let state = Arc::downgrade(&self.state);
+                let kick = move |id: usize| {
+                    if let Some(state) = state.upgrade() {
+                        tokio::spawn(async move { state.kick(id).await });
+                    }
+                };
+                AdminPanel::new(
+                    self.state.registry.clone(),
+                    Box::new(kick),
+                    self.id,
+                    std::time::Duration::from_secs(self.state.cli.broadcast_secs),
+                )
+            }),
+            fps: self.state.cli.fps,
+            fps_cap: u32::MAX,
+            play_area: None,
+            last_update: None,
+            glyphs: self.caps.best_glyphs(self.state.cli.braille),
+            color_depth: self.caps.color_depth(),
+            image_protocol: self.caps.image_protocol(),
+            image_at: None,
+            image_sprites: None,
+            kitty_placed: None,
+            sprite_fraction: self.state.cli.sprite_fraction,
+            canvas_limit: (self.state.cli.canvas_cols, self.state.cli.canvas_rows),
+            scaled_sprites: None,
+            zoom: 1,
+            background: self.state.config_file.background,
+            day_night: self.state.config_file.day_night,
+            tint: None,
+            season: Season::pick(
+                self.state.config_file.season.as_deref(),
+                chrono::Local::now().month(),
+            ),
+            corner_hits: 0,
+            corner_counter: self.state.corner_counter.clone(),
+            celebration: None,
+            show_stats: false,
+            session_stats: None,
+            status_bar: self
+                .state
+                .config_file
+                .status_bar
+                .enabled
+                .then(|| StatusBar::new(self.state.registry.clone(), self.state.started_at)),
+            clock: self.state.config_file.clock.clone(),
+            marquee: self.state.config_file.marquee.clone().map(Marquee::new),
+            quips,
+            particles: Particles::default(),
+            rainbow_since: None,
+            grayscale: false,
+            high_contrast: profile
+                .and_then(|profile| profile.theme)
+                .unwrap_or(self.state.config_file.theme)
+                == Theme::HighContrast,
+            reduced_motion,
+            bell: self.state.config_file.bell,
+            bell_pending: false,
+            gravity: false,
+            physics,
+            scared: self.state.config_file.scared,
+        }
+    }
+
+    // The terminal and App only exist once the client asks for a pty.
+    async fn open_client(&self, output: Output, rect: Rect) -> Result<Client, anyhow::Error> {
+        let terminal_handle = TerminalHandle::start(output.clone(), self.bytes_sent.clone()).await;
+        let alive = terminal_handle.alive.clone();
+        let forwarder = terminal_handle.forwarder.clone();
+        let frames = terminal_handle.frames.clone();
+        let stats = self.state.registry.register(
+            self.id,
+            self.peer_addr,
+            &self.user,
+            terminal_handle.bytes_sent.clone(),
+        );
+        stats.set_caps(self.caps.clone());
+
+        let backend = CrosstermBackend::new(terminal_handle);
+        let options = TerminalOptions {
+            viewport: Viewport::Fixed(rect),
+        };
+        let terminal = Terminal::with_options(backend, options)?;
+
+        let mut app = self.new_app();
+        app.session_stats = Some(stats.clone());
+
+        Ok(Client {
+            terminal,
+            app,
+            output,
+            alive,
+            uploader: self.uploader(),
+            profile: self.fingerprint.clone().zip(self.state.profiles.clone()),
+            stats,
+            forwarder,
+            frames,
+            render: None,
+            too_large: false,
+            adapted_at: std::time::Instant::now(),
+            pending_resize: None,
+            last_frame: Buffer::default(),
+            json_frame: None,
+        })
+    }
+
+    fn new_sftp(&self) -> SpriteSftp {
+        let state = self.state.clone();
+
+        // The uploader's live sessions pick up the new sprites right away.
+        let on_upload = move |uploader: &Uploader| {
+            let pixel_maps = state.sprites(uploader);
+            let state = state.clone();
+            let uploader = uploader.clone();
+            tokio::spawn(async move {
+                let clients: Vec<SharedClient> =
+                    state.clients.lock().await.values().cloned().collect();
+                for client in clients {
+                    let mut client = client.lock().await;
+                    if client.uploader == uploader {
+                        client.app.set_sprites(pixel_maps.clone());
+                    }
+                }
+            });
+        };
+
+        // Only the moods with a file of their own can be fetched or replaced.
+        let files = Mood::ALL
+            .into_iter()
+            .filter_map(|mood| {
+                let path = sprite_path(&self.state.cli, mood)?;
+                Some((mood.sprite_file().to_string(), path.to_string()))
+            })
+            .collect();
+        SpriteSftp::new(
+            files,
+            self.uploader(),
+            self.state.uploads.clone(),
+            Box::new(on_upload),
+        )
+    }
+
+    async fn exec_kick(&self, id: &str) -> (String, u32) {
+        match id.trim().parse::<usize>() {
+            Ok(id) if self.state.kick(id).await => (format!("kicked session {id}\n"), 0),
+            Ok(id) => (format!("no session {id}\n"), 1),
+            Err(_) => (format!("not a session id: {id}\n"), 1),
+        }
+    }
+
+    // `sessions` is how many are playing and how many are waiting.
+    fn exec_output(&self, command: &str, sessions: (usize, usize)) -> (String, u32) {
+        let help = "commands:\n  version  print the server version\n  stats    print server statistics\n  help     show this message\n  kick ID  end a session (admin only)\n  drain    stop taking connections, exit when empty (admin only)\n";
+        match command {
+            "version" => (format!("robert-ssh {}\n", env!("CARGO_PKG_VERSION")), 0),
+            "stats" => (
+                format!(
+                    "sessions: {}\nwaiting: {}\nuptime: {}s\n",
+                    sessions.0,
+                    sessions.1,
+                    self.state.started_at.elapsed().as_secs()
+                ),
+                0,
+            ),
+            "help" => (help.to_string(), 0),
+            _ => (format!("unknown command: {command}\n{help}"), 1),
         }
+    }
 
-        self.sink.clear();
+    // Starts robert on `output`, along with the task that draws him.
+    async fn start_session(
+        &self,
+        output: Output,
+        rect: Rect,
+        too_large: bool,
+    ) -> Result<(), anyhow::Error> {
+        let mut client = self.open_client(output, rect).await?;
+        client.too_large = too_large;
+        self.launch(client).await;
         Ok(())
     }
-}
 
-#[derive(Clone)]
-pub struct AppServer {
-    clients: Arc<Mutex<HashMap<usize, (SshTerminal, App)>>>,
-    id: usize,
-}
+    // Admits `client` like any other session, capacity, waiting room and all, and starts
+    // the task that draws it.
+    async fn launch(&self, client: Client) {
+        let client = Arc::new(Mutex::new(client));
 
-impl AppServer {
-    pub fn new() -> Self {
-        Self {
-            clients: Arc::new(Mutex::new(HashMap::new())),
-            id: 0,
+        // In the map and the line before the first frame, so it's never drawn ahead of
+        // its turn.
+        if self.state.add_client(self.id, client.clone()).await {
+            println!("Server full, session {} waiting in line", self.id);
         }
+        let render = tokio::spawn(render_session(
+            self.state.clone(),
+            self.id,
+            Arc::downgrade(&client),
+        ));
+        client.lock().await.render = Some(render.abort_handle());
     }
 
-    fn load_host_keys() -> Result<russh::keys::PrivateKey, anyhow::Error> {
-        let secrets_location =
-            env::var("SECRETS_LOCATION").expect("SECRETS_LOCATION was not defined.");
-        let key_path = Path::new(&secrets_location);
+    // Hands keystrokes to the session. True if they asked to leave, in which case the
+    // session is already gone and only the connection is left to close.
+    async fn input(&self, data: &[u8]) -> bool {
+        let client = self.client().await;
+        let typing = match &client {
+            Some(client) => client.lock().await.app.is_typing(),
+            None => false,
+        };
 
-        if !key_path.exists() {
-            return Err(anyhow::anyhow!(
-                "Host key not found at {}. Please generate host keys first.",
-                key_path.display()
-            ));
+        // Pressing 'q' closes the connection, unless it's part of something being typed.
+        if data == b"q" && !typing {
+            self.state.remove_client(self.id).await;
+            return true;
+        }
+
+        if let Some(client) = client {
+            client.lock().await.app.handle_input(data);
         }
+        false
+    }
 
-        let key = russh::keys::PrivateKey::read_openssh_file(key_path)
-            .map_err(|e| anyhow::anyhow!("Failed to read host key: {}", e))?;
+    async fn resize(&self, col_width: u32, row_height: u32) -> Result<(), anyhow::Error> {
+        self.audit(AuditEvent {
+            width: Some(col_width),
+            height: Some(row_height),
+            ..AuditEvent::new("resize", self.id)
+        });
 
-        Ok(key)
+        let (rect, too_large) = self.terminal_rect(col_width, row_height);
+        if let Some(client) = self.client().await {
+            client.lock().await.pending_resize = Some((rect, too_large, std::time::Instant::now()));
+        }
+        Ok(())
     }
 
-    pub async fn run(&mut self) -> Result<(), anyhow::Error> {
-        let clients = self.clients.clone();
-        tokio::spawn(async move {
-            loop {
-                tokio::time::sleep(tokio::time::Duration::from_millis(1000 / 30)).await;
+    async fn serve(self, transport: Transport, stream: TcpStream) {
+        match transport {
+            Transport::Telnet => self.run_telnet(stream).await,
+            Transport::Ansi => self.run_ansi(stream).await,
+            Transport::Web => self.run_web(stream).await,
+        }
+    }
+
+    // Serves a telnet connection. There's no login, so it goes straight to robert.
+    async fn run_telnet(mut self, stream: TcpStream) {
+        if self.refuse_over_limit() {
+            return;
+        }
+
+        let (reader, mut writer) = stream.into_split();
+        // Written before the writer is wrapped, since `Output` escapes telnet commands.
+        if writer.write_all(telnet::NEGOTIATION).await.is_err() {
+            return;
+        }
+        let output = Output::Telnet(Arc::new(Mutex::new(writer)));
+
+        self.user = "telnet".to_string();
+        self.client_version = Some("telnet".to_string());
+        self.run_stream(output, telnet::Reader::new(reader)).await;
+    }
+
+    // Serves a connection to the web port, which is either the page or the WebSocket
+    // it opens. Like telnet, there's no login.
+    async fn run_web(mut self, stream: TcpStream) {
+        let accepted = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            websocket::accept(stream),
+        );
+        let (sink, reader, user_agent) = match accepted.await {
+            Ok(Ok(Some(websocket))) => websocket,
+            Ok(Ok(None)) | Err(_) => return,
+            Ok(Err(e)) => {
+                eprintln!("Failed to serve web connection: {e}");
+                return;
+            }
+        };
+        if self.refuse_over_limit() {
+            return;
+        }
+
+        self.user = "web".to_string();
+        self.client_version = Some(user_agent.unwrap_or_else(|| "web".to_string()));
+        let output = Output::WebSocket(Arc::new(Mutex::new(sink)));
+        self.run_stream(output, reader).await;
+    }
+
+    // Streams robert to a plain TCP client, over HTTP for curl or bare for nc. Whatever
+    // it types still reaches the session, so 'q' works from nc.
+    async fn run_ansi(mut self, stream: TcpStream) {
+        let accepted = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            ansi_stream::accept(stream),
+        );
+        let (reader, writer, user_agent) = match accepted.await {
+            Ok(Ok(Some(accepted))) => accepted,
+            Ok(Ok(None)) | Err(_) => return,
+            Ok(Err(e)) => {
+                eprintln!("Failed to serve ANSI connection: {e}");
+                return;
+            }
+        };
+        if self.refuse_over_limit() {
+            return;
+        }
+
+        self.user = "ansi".to_string();
+        self.client_version = Some(user_agent.unwrap_or_else(|| "tcp".to_string()));
+        let output = Output::Raw(Arc::new(Mutex::new(writer)));
+        self.run_stream(output, reader).await;
+    }
+
+    fn refuse_over_limit(&self) -> bool {
+        if self.over_ip_limit
+            && let Some(ip) = self.peer_ip
+        {
+            println!("Refusing session from {ip}: too many connections from this address");
+        }
+        self.over_ip_limit
+    }
 
-                for (_, (terminal, app)) in clients.lock().await.iter_mut() {
-                    terminal.draw(|f| app.draw(f)).unwrap();
+    // Runs robert for a telnet or browser client until it leaves or its session ends.
+    async fn run_stream(mut self, output: Output, mut input: impl InputSource) {
+        // Most clients report their size straight away. Wait a moment for it so the first
+        // frame fits, but don't count on it.
+        let first_size = tokio::time::timeout(FIRST_SIZE_TIMEOUT, async {
+            loop {
+                let size = input
+                    .read()
+                    .await?
+                    .into_iter()
+                    .find_map(|event| match event {
+                        InputEvent::Resize(cols, rows) => Some((cols, rows)),
+                        InputEvent::Keys(_) => None,
+                    });
+                if size.is_some() {
+                    return size;
                 }
             }
+        })
+        .await;
+        let (cols, rows) = match first_size {
+            Ok(Some(size)) => size,
+            Ok(None) => return,
+            Err(_) => (80, 24),
+        };
+        self.audit(AuditEvent {
+            width: Some(cols.into()),
+            height: Some(rows.into()),
+            ..AuditEvent::new("pty", self.id)
         });
 
-        let mut methods = MethodSet::empty();
-        methods.push(MethodKind::None);
+        let (rect, too_large) = self.terminal_rect(cols.into(), rows.into());
+        let _ = output
+            .send([ENTER_ALT_SCREEN, HIDE_CURSOR, SET_TITLE].concat())
+            .await;
+        if let Err(e) = self.start_session(output.clone(), rect, too_large).await {
+            eprintln!("Failed to start {} session: {e}", self.user);
+            output.close().await;
+            return;
+        }
 
-        let host_key = Self::load_host_keys()
-            .map_err(|e| anyhow::anyhow!("Failed to load host keys: {}", e))?;
+        loop {
+            // Checked every so often, so a client that never hangs up after its session
+            // ends doesn't hold on to the connection.
+            let read = tokio::time::timeout(std::time::Duration::from_secs(1), input.read());
+            let events = match read.await {
+                Ok(Some(events)) => events,
+                Ok(None) => break,
+                Err(_) if self.client().await.is_none() => break,
+                Err(_) => continue,
+            };
 
-        let config = Config {
-            inactivity_timeout: Some(std::time::Duration::from_secs(3600)),
-            auth_rejection_time: std::time::Duration::from_secs(3),
-            auth_rejection_time_initial: Some(std::time::Duration::from_secs(0)),
-            methods,
-            keys: vec![host_key],
-            nodelay: true,
-            ..Default::default()
+            for event in events {
+                match event {
+                    InputEvent::Keys(data) => {
+                        self.bytes_received += data.len() as u64;
+                        if self.input(&data).await {
+                            let reset_sequence =
+                                [EXIT_ALT_SCREEN, SHOW_CURSOR, RESTORE_TITLE, MOUSE_OFF].concat();
+                            let _ = output.send(reset_sequence).await;
+                            output.close().await;
+                            return;
+                        }
+                    }
+                    InputEvent::Resize(cols, rows) => {
+                        if let Err(e) = self.resize(cols.into(), rows.into()).await {
+                            eprintln!("Failed to resize {} session: {e}", self.user);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // The area drawn on for a window of the given size, capped at --max-cols/--max-rows
+    // so a huge pty can't make every frame huge. True if the cap applied.
+    fn terminal_rect(&self, col_width: u32, row_height: u32) -> (Rect, bool) {
+        let width = col_width.min(u32::from(self.state.cli.max_cols));
+        let height = row_height.min(u32::from(self.state.cli.max_rows));
+        let rect = Rect {
+            x: 0,
+            y: 0,
+            width: width as u16,
+            height: height as u16,
         };
+        (rect, width < col_width || height < row_height)
+    }
 
-        println!("Starting server on port 2222");
-        self.run_on_address(Arc::new(config), ("0.0.0.0", 2222))
-            .await?;
-        Ok(())
+    // This connection's session, if it has one. The map lock is only held long enough
+    // to find it.
+    async fn client(&self) -> Option<SharedClient> {
+        self.state.clients.lock().await.get(&self.id).cloned()
+    }
+
+    // russh queues incoming data on every `Channel` we hold, so once a channel is known
+    // not to be SFTP it has to be dropped or the queue fills up and stalls the session.
+    async fn release_channel(&mut self, channel: ChannelId) -> Option<Channel<Msg>> {
+        self.channels.remove(&channel)
     }
-}
 
-impl Server for AppServer {
-    type Handler = Self;
-    fn new_client(&mut self, _: Option<std::net::SocketAddr>) -> Self {
-        let s = self.clone();
-        self.id += 1;
-        s
+    fn record_auth_failure(&self) {
+        if let Some(ip) = self.peer_ip {
+            self.state.bans.lock().unwrap().record_auth_failure(ip);
+        }
     }
 }
 
-impl Handler for AppServer {
+impl Handler for ConnectionHandler {
     type Error = anyhow::Error;
 
     async fn channel_open_session(
         &mut self,
         channel: Channel<Msg>,
-        session: &mut Session,
+        _: &mut Session,
     ) -> Result<bool, Self::Error> {
-        let terminal_handle = TerminalHandle::start(session.handle(), channel.id()).await;
+        if self.over_ip_limit {
+            if let Some(ip) = self.peer_ip {
+                println!("Refusing session from {ip}: too many connections from this address");
+            }
+            return Ok(false);
+        }
+        self.channels.insert(channel.id(), channel);
+        Ok(true)
+    }
 
-        let backend = CrosstermBackend::new(terminal_handle);
+    async fn authentication_banner(&mut self) -> Result<Option<String>, Self::Error> {
+        let banner = self.state.cli.banner.as_ref().map(|banner| {
+            if banner.ends_with('\n') {
+                banner.clone()
+            } else {
+                format!("{banner}\n")
+            }
+        });
+        Ok(banner)
+    }
 
-        // the correct viewport area will be set when the client request a pty
-        let options = TerminalOptions {
-            viewport: Viewport::Fixed(Rect::default()),
-        };
+    async fn auth_none(&mut self, user: &str) -> Result<Auth, Self::Error> {
+        self.user = user.to_string();
+        if !self.state.is_anonymous() {
+            return Ok(Auth::reject());
+        }
+        Ok(Auth::Accept)
+    }
 
-        let terminal = Terminal::with_options(backend, options)?;
-        let app = App {
-            offset: (0.0, 0.0),
-            sx: -1.5,
-            sy: -1.0,
-            normal_pixel_map: load_to_pixel_map("./normal.png"),
-            scared_pixel_map: load_to_pixel_map("./scared.png"),
-            rng: StdRng::from_entropy(),
-        };
+    async fn auth_password(&mut self, user: &str, password: &str) -> Result<Auth, Self::Error> {
+        self.user = user.to_string();
+        if self.state.verify_password(user, password) {
+            Ok(Auth::Accept)
+        } else {
+            self.record_auth_failure();
+            Ok(Auth::reject())
+        }
+    }
 
-        tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+    async fn auth_publickey_offered(
+        &mut self,
+        _: &str,
+        public_key: &PublicKey,
+    ) -> Result<Auth, Self::Error> {
+        // russh offers a certificate as its bare key, so with a CA configured any key is
+        // let through to sign. The certificate itself is checked once it has.
+        if self.state.is_authorized(public_key) || self.state.trusted_cas.is_some() {
+            Ok(Auth::Accept)
+        } else {
+            Ok(Auth::reject())
+        }
+    }
 
-        let mut clients = self.clients.lock().await;
-        clients.insert(self.id, (terminal, app));
+    async fn auth_publickey(
+        &mut self,
+        user: &str,
+        public_key: &PublicKey,
+    ) -> Result<Auth, Self::Error> {
+        self.user = user.to_string();
+        if self.state.is_authorized(public_key) {
+            self.fingerprint = Some(public_key.fingerprint(HashAlg::Sha256).to_string());
+            Ok(Auth::Accept)
+        } else {
+            self.record_auth_failure();
+            Ok(Auth::reject())
+        }
+    }
 
-        Ok(true)
+    async fn auth_openssh_certificate(
+        &mut self,
+        user: &str,
+        certificate: &Certificate,
+    ) -> Result<Auth, Self::Error> {
+        self.user = user.to_string();
+        if self.state.is_trusted_certificate(user, certificate) {
+            println!(
+                "Accepted certificate \"{}\" for {user}",
+                certificate.key_id()
+            );
+            let public_key = PublicKey::new(certificate.public_key().clone(), "");
+            self.fingerprint = Some(public_key.fingerprint(HashAlg::Sha256).to_string());
+            Ok(Auth::Accept)
+        } else {
+            self.record_auth_failure();
+            Ok(Auth::reject())
+        }
     }
 
-    async fn auth_none(&mut self, _: &str) -> Result<Auth, Self::Error> {
-        Ok(Auth::Accept)
+    async fn auth_succeeded(&mut self, session: &mut Session) -> Result<(), Self::Error> {
+        self.client_version = Some(String::from_utf8_lossy(session.remote_sshid()).into_owned());
+        self.audit(AuditEvent::new("auth", self.id));
+        Ok(())
     }
 
     async fn data(
@@ -186,17 +2172,26 @@ impl Handler for AppServer {
         data: &[u8],
         session: &mut Session,
     ) -> Result<(), Self::Error> {
-        match data {
-            // Pressing 'q' closes the connection.
-            b"q" => {
-                let reset_sequence = [EXIT_ALT_SCREEN, SHOW_CURSOR].concat();
-                let _ = session.data(channel, reset_sequence.into());
+        self.bytes_received += data.len() as u64;
+        // Keystrokes only mean something on the channel robert is drawn on.
+        if self.terminal_channel != Some(channel) {
+            return Ok(());
+        }
 
-                self.clients.lock().await.remove(&self.id);
-                session.close(channel)?;
+        // Answers to the queries sent with the pty, not something typed.
+        if self.caps.read_replies(data) {
+            if let Some(client) = self.client().await {
+                let mut client = client.lock().await;
+                client.stats.set_caps(self.caps.clone());
+                client.app.set_image_protocol(self.caps.image_protocol());
             }
+            return Ok(());
+        }
 
-            _ => {}
+        if self.input(data).await {
+            let reset_sequence = [EXIT_ALT_SCREEN, SHOW_CURSOR, RESTORE_TITLE, MOUSE_OFF].concat();
+            let _ = session.data(channel, reset_sequence.into());
+            session.close(channel)?;
         }
 
         Ok(())
@@ -211,24 +2206,13 @@ impl Handler for AppServer {
         _: u32,
         _: &mut Session,
     ) -> Result<(), Self::Error> {
-        let rect = Rect {
-            x: 0,
-            y: 0,
-            width: col_width as u16,
-            height: row_height as u16,
-        };
-
-        let mut clients = self.clients.lock().await;
-        let (terminal, _) = clients.get_mut(&self.id).unwrap();
-        terminal.resize(rect)?;
-
-        Ok(())
+        self.resize(col_width, row_height).await
     }
 
     async fn pty_request(
         &mut self,
         channel: ChannelId,
-        _: &str,
+        term: &str,
         col_width: u32,
         row_height: u32,
         _: u32,
@@ -236,31 +2220,168 @@ impl Handler for AppServer {
         _: &[(Pty, u32)],
         session: &mut Session,
     ) -> Result<(), Self::Error> {
-        let rect = Rect {
-            x: 0,
-            y: 0,
-            width: col_width as u16,
-            height: row_height as u16,
-        };
+        let (rect, too_large) = self.terminal_rect(col_width, row_height);
 
-        let mut clients = self.clients.lock().await;
-        let (terminal, _) = clients.get_mut(&self.id).unwrap();
-        terminal.resize(rect)?;
+        self.audit(AuditEvent {
+            term: Some(term.to_string()),
+            width: Some(col_width),
+            height: Some(row_height),
+            ..AuditEvent::new("pty", self.id)
+        });
+
+        self.pty_requested = true;
+        self.terminal_channel = Some(channel);
+        if !term.is_empty() {
+            self.caps.term = Some(term.to_string());
+        }
+        self.release_channel(channel).await;
 
+        let output = Output::Ssh(session.handle(), channel);
         session.channel_success(channel)?;
+        let setup_sequence = [ENTER_ALT_SCREEN, HIDE_CURSOR, SET_TITLE, TERMINAL_QUERIES].concat();
+        let _ = session.data(channel, setup_sequence.into());
 
-        if let Err(e) = session
-            .handle()
-            .data(channel, ENTER_ALT_SCREEN.into())
-            .await
-        {
-            eprintln!("Failed to enter alternate screen: {:?}", e);
+        self.start_session(output, rect, too_large).await
+    }
+
+    // OpenSSH sends these after the pty request, so a running session is updated too.
+    async fn env_request(
+        &mut self,
+        channel: ChannelId,
+        variable_name: &str,
+        variable_value: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        if !self.caps.set_env(variable_name, variable_value) {
+            session.channel_failure(channel)?;
+            return Ok(());
+        }
+
+        if let Some(client) = self.client().await {
+            let mut client = client.lock().await;
+            client.stats.set_caps(self.caps.clone());
+            client.app.glyphs = self.caps.best_glyphs(self.state.cli.braille);
+            client.app.color_depth = self.caps.color_depth();
+        }
+        session.channel_success(channel)?;
+        Ok(())
+    }
+
+    async fn subsystem_request(
+        &mut self,
+        channel: ChannelId,
+        name: &str,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        if name == "sftp" {
+            let Some(sftp_channel) = self.release_channel(channel).await else {
+                session.channel_failure(channel)?;
+                return Ok(());
+            };
+            session.channel_success(channel)?;
+            self.sftp_channels.insert(channel);
+            russh_sftp::server::run(sftp_channel.into_stream(), self.new_sftp()).await;
+            return Ok(());
+        }
+
+        self.release_channel(channel).await;
+        // One session to a connection, whether it's drawn or streamed.
+        if name != "robert" || self.terminal_channel.is_some() || self.refuse_over_limit() {
+            session.channel_failure(channel)?;
+            return Ok(());
         }
+        session.channel_success(channel)?;
+        self.terminal_channel = Some(channel);
+
+        // A session like any other, but streaming one JSON object per frame, simulated on
+        // a fixed 80x24 area, until the client goes away. It isn't saved to a profile,
+        // since nobody picks anything in it.
+        let rect = Rect::new(0, 0, 80, 24);
+        let mut client = self
+            .open_client(Output::Ssh(session.handle(), channel), rect)
+            .await?;
+        client.app.scene = Scene::Bouncing;
+        client.app.play_area = Some(rect);
+        client.json_frame = Some(0);
+        client.profile = None;
+        self.launch(client).await;
+        Ok(())
+    }
+
+    async fn shell_request(
+        &mut self,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        session.channel_success(channel)?;
+        self.release_channel(channel).await;
 
-        if let Err(e) = session.handle().data(channel, HIDE_CURSOR.into()).await {
-            eprintln!("Failed to hide cursor: {:?}", e);
+        if self.pty_requested {
+            return Ok(());
         }
 
+        // No pty (e.g. `ssh host < /dev/null`): there is nothing to animate on, so send a
+        // still frame and hang up.
+        let message = if self.state.is_full().await {
+            "robert is busy right now, please try again later\n".to_string()
+        } else {
+            format!(
+                "{}robert needs an interactive terminal, try `ssh -t`\n",
+                ansi_snapshot(&self.state.cli.normal)
+            )
+        };
+        session.data(channel, message.into_bytes().into())?;
+        session.exit_status_request(channel, 0)?;
+        session.eof(channel)?;
+        session.close(channel)?;
+
+        Ok(())
+    }
+
+    async fn exec_request(
+        &mut self,
+        channel: ChannelId,
+        data: &[u8],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.release_channel(channel).await;
+        let command = String::from_utf8_lossy(data);
+        let command = command.trim();
+        let (output, exit_status) = match command.strip_prefix("kick ") {
+            Some(id) if self.is_admin() => self.exec_kick(id).await,
+            _ if command == "drain" && self.is_admin() => {
+                self.state.drain.notify_one();
+                (
+                    "draining, the server exits once every session has left\n".to_string(),
+                    0,
+                )
+            }
+            _ => {
+                let sessions = self.state.session_counts().await;
+                self.exec_output(command, sessions)
+            }
+        };
+
+        session.channel_success(channel)?;
+        session.data(channel, output.into_bytes().into())?;
+        session.exit_status_request(channel, exit_status)?;
+        session.eof(channel)?;
+        session.close(channel)?;
+
+        Ok(())
+    }
+
+    // sftp and scp clients send EOF when they're done and wait for an exit status.
+    async fn channel_eof(
+        &mut self,
+        channel: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        if self.sftp_channels.remove(&channel) {
+            session.exit_status_request(channel, 0)?;
+            session.eof(channel)?;
+            session.close(channel)?;
+        }
         Ok(())
     }
 
@@ -269,21 +2390,79 @@ impl Handler for AppServer {
         channel: ChannelId,
         session: &mut Session,
     ) -> Result<(), Self::Error> {
-        let reset_sequence = [EXIT_ALT_SCREEN, SHOW_CURSOR].concat();
+        self.release_channel(channel).await;
+        // Other channels on the connection, like an exec or sftp, leave the session be.
+        if self.terminal_channel != Some(channel) {
+            return Ok(());
+        }
+
+        let reset_sequence = [EXIT_ALT_SCREEN, SHOW_CURSOR, RESTORE_TITLE, MOUSE_OFF].concat();
         let _ = session.data(channel, reset_sequence.into());
 
-        self.clients.lock().await.remove(&self.id);
+        self.terminal_channel = None;
+        self.state.remove_client(self.id).await;
         Ok(())
     }
+
+    // Global requests without a callback here, `keepalive@openssh.com` among them, are
+    // answered by russh with a failure. That still counts as a reply to OpenSSH, so
+    // ServerAliveInterval keeps working. Forwarding is refused too, but logged, since a
+    // client asking for it is usually misconfigured or probing.
+    async fn tcpip_forward(
+        &mut self,
+        address: &str,
+        port: &mut u32,
+        _: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        println!(
+            "Refusing remote forward of {address}:{port} for session {}",
+            self.id
+        );
+        Ok(false)
+    }
+
+    async fn streamlocal_forward(
+        &mut self,
+        socket_path: &str,
+        _: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        println!(
+            "Refusing remote forward of {socket_path} for session {}",
+            self.id
+        );
+        Ok(false)
+    }
 }
 
-impl Drop for AppServer {
+impl Drop for ConnectionHandler {
     fn drop(&mut self) {
+        self.audit(AuditEvent {
+            duration_secs: Some(self.connected_at.elapsed().as_secs_f64()),
+            bytes_sent: Some(self.bytes_sent.load(Ordering::Relaxed)),
+            bytes_received: Some(self.bytes_received),
+            ..AuditEvent::new("disconnect", self.id)
+        });
+
+        if let Some(ip) = self.peer_ip {
+            let mut connections = self.state.connections_per_ip.lock().unwrap();
+            if let Some(count) = connections.get_mut(&ip) {
+                *count -= 1;
+                if *count == 0 {
+                    connections.remove(&ip);
+                }
+            }
+        }
+
+        self.state
+            .uploads
+            .lock()
+            .unwrap()
+            .remove(&Uploader::Connection(self.id));
+
         let id = self.id;
-        let clients = self.clients.clone();
+        let state = self.state.clone();
         tokio::spawn(async move {
-            let mut clients = clients.lock().await;
-            clients.remove(&id);
+            state.remove_client(id).await;
         });
     }
 }
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use russh_sftp::protocol::{
+    Attrs, Data, File, FileAttributes, FileMode, Handle, Name, OpenFlags, Status, StatusCode,
+    Version,
+};
+
+use crate::app::pixel_map_from_png;
+
+const MAX_UPLOAD_BYTES: usize = 64 * 1024;
+// Uploads one SFTP session can have open at once.
+const MAX_OPEN_UPLOADS: usize = 8;
+
+// Whose sprites an upload replaces: every session signed in with the same key, or
+// without one, only the sessions on the connection it came in on.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Uploader {
+    Key(String),
+    Connection(usize),
+}
+
+impl std::fmt::Display for Uploader {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Key(fingerprint) => write!(f, "key {fingerprint}"),
+            Self::Connection(id) => write!(f, "connection {id}"),
+        }
+    }
+}
+
+// Sprites uploaded by each uploader, by file name. A connection's go when it closes, a
+// key's stay for the next time it signs in.
+pub type Uploads = Arc<std::sync::Mutex<HashMap<Uploader, HashMap<String, Vec<u8>>>>>;
+
+// An SFTP view of the sprite images, served as files in `/`. Overwriting one replaces it
+// for the uploader's own sessions only.
+pub struct SpriteSftp {
+    // File name as seen by the client, and where it lives on disk.
+    files: Vec<(String, String)>,
+    uploader: Uploader,
+    uploads: Uploads,
+    // Called with the uploader once a new sprite is in place.
+    on_upload: Box<dyn Fn(&Uploader) + Send>,
+    open_files: HashMap<String, Vec<u8>>,
+    // Uploads in progress: target file name and the bytes received so far.
+    open_uploads: HashMap<String, (String, Vec<u8>)>,
+    // Whether each open directory handle has already been listed.
+    open_dirs: HashMap<String, bool>,
+    next_handle: u64,
+}
+
+impl SpriteSftp {
+    pub fn new(
+        files: Vec<(String, String)>,
+        uploader: Uploader,
+        uploads: Uploads,
+        on_upload: Box<dyn Fn(&Uploader) + Send>,
+    ) -> Self {
+        Self {
+            files,
+            uploader,
+            uploads,
+            on_upload,
+            open_files: HashMap::new(),
+            open_uploads: HashMap::new(),
+            open_dirs: HashMap::new(),
+            next_handle: 0,
+        }
+    }
+
+    fn new_handle(&mut self) -> String {
+        self.next_handle += 1;
+        self.next_handle.to_string()
+    }
+
+    // Every path is relative to `/`, so `normal.png`, `/normal.png` and `./normal.png`
+    // all name the same file. None means the root directory.
+    fn file_name(path: &str) -> Option<&str> {
+        let name = path.trim_start_matches("./").trim_start_matches('/');
+        if name.is_empty() || name == "." {
+            None
+        } else {
+            Some(name)
+        }
+    }
+
+    fn disk_path(&self, name: &str) -> Option<&str> {
+        self.files
+            .iter()
+            .find(|(file_name, _)| file_name == name)
+            .map(|(_, path)| path.as_str())
+    }
+
+    fn uploaded(&self, name: &str) -> Option<Vec<u8>> {
+        let uploads = self.uploads.lock().unwrap();
+        uploads.get(&self.uploader)?.get(name).cloned()
+    }
+
+    // The uploaded copy if there is one, otherwise the file on disk.
+    fn contents(&self, name: &str) -> Result<Vec<u8>, StatusCode> {
+        if let Some(data) = self.uploaded(name) {
+            return Ok(data);
+        }
+        let disk_path = self.disk_path(name).ok_or(StatusCode::NoSuchFile)?;
+        std::fs::read(disk_path).map_err(|_| StatusCode::NoSuchFile)
+    }
+
+    fn dir_attrs() -> FileAttributes {
+        FileAttributes {
+            permissions: Some(0o755 | FileMode::DIR.bits()),
+            ..Default::default()
+        }
+    }
+
+    fn data_attrs(data: &[u8]) -> FileAttributes {
+        FileAttributes {
+            size: Some(data.len() as u64),
+            permissions: Some(0o644 | FileMode::REG.bits()),
+            ..Default::default()
+        }
+    }
+
+    fn file_attrs(&self, name: &str) -> Result<FileAttributes, StatusCode> {
+        if let Some(data) = self.uploaded(name) {
+            return Ok(Self::data_attrs(&data));
+        }
+        let disk_path = self.disk_path(name).ok_or(StatusCode::NoSuchFile)?;
+        let metadata = std::fs::metadata(disk_path).map_err(|_| StatusCode::NoSuchFile)?;
+        let mut attrs = FileAttributes::from(&metadata);
+        attrs.permissions = Some(0o644 | FileMode::REG.bits());
+        Ok(attrs)
+    }
+
+    fn path_attrs(&self, path: &str) -> Result<FileAttributes, StatusCode> {
+        match Self::file_name(path) {
+            None => Ok(Self::dir_attrs()),
+            Some(name) => self.file_attrs(name),
+        }
+    }
+
+    fn status(id: u32, status_code: StatusCode, message: &str) -> Status {
+        Status {
+            id,
+            status_code,
+            error_message: message.to_string(),
+            language_tag: "en-US".to_string(),
+        }
+    }
+
+    // Checks a finished upload and, if it's a usable sprite, swaps it in.
+    fn finish_upload(&mut self, id: u32, name: String, data: Vec<u8>) -> Status {
+        let uploader = &self.uploader;
+        if let Err(e) = pixel_map_from_png(&data, None) {
+            println!("Rejected {name} upload from {uploader}: {e}");
+            return Self::status(id, StatusCode::Failure, &format!("{name} rejected: {e}"));
+        }
+
+        println!("{uploader} uploaded a custom {name}");
+        self.uploads
+            .lock()
+            .unwrap()
+            .entry(uploader.clone())
+            .or_default()
+            .insert(name, data);
+        (self.on_upload)(uploader);
+        Self::status(id, StatusCode::Ok, "Ok")
+    }
+}
+
+impl russh_sftp::server::Handler for SpriteSftp {
+    type Error = StatusCode;
+
+    fn unimplemented(&self) -> Self::Error {
+        StatusCode::OpUnsupported
+    }
+
+    async fn init(&mut self, _: u32, _: HashMap<String, String>) -> Result<Version, Self::Error> {
+        Ok(Version::new())
+    }
+
+    async fn realpath(&mut self, id: u32, path: String) -> Result<Name, Self::Error> {
+        let path = match Self::file_name(&path) {
+            Some(name) => format!("/{name}"),
+            None => "/".to_string(),
+        };
+        Ok(Name {
+            id,
+            files: vec![File::dummy(path)],
+        })
+    }
+
+    async fn stat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        let attrs = self.path_attrs(&path)?;
+        Ok(Attrs { id, attrs })
+    }
+
+    async fn lstat(&mut self, id: u32, path: String) -> Result<Attrs, Self::Error> {
+        let attrs = self.path_attrs(&path)?;
+        Ok(Attrs { id, attrs })
+    }
+
+    async fn fstat(&mut self, id: u32, handle: String) -> Result<Attrs, Self::Error> {
+        if self.open_dirs.contains_key(&handle) {
+            return Ok(Attrs {
+                id,
+                attrs: Self::dir_attrs(),
+            });
+        }
+        let data = match self.open_uploads.get(&handle) {
+            Some((_, data)) => data,
+            None => self.open_files.get(&handle).ok_or(StatusCode::Failure)?,
+        };
+        Ok(Attrs {
+            id,
+            attrs: Self::data_attrs(data),
+        })
+    }
+
+    async fn opendir(&mut self, id: u32, path: String) -> Result<Handle, Self::Error> {
+        if Self::file_name(&path).is_some() {
+            return Err(StatusCode::NoSuchFile);
+        }
+        let handle = self.new_handle();
+        self.open_dirs.insert(handle.clone(), false);
+        Ok(Handle { id, handle })
+    }
+
+    async fn readdir(&mut self, id: u32, handle: String) -> Result<Name, Self::Error> {
+        let listed = self.open_dirs.get_mut(&handle).ok_or(StatusCode::Failure)?;
+        if *listed {
+            return Err(StatusCode::Eof);
+        }
+        *listed = true;
+
+        let files = self
+            .files
+            .iter()
+            .filter_map(|(name, _)| Some(File::new(name, self.file_attrs(name).ok()?)))
+            .collect();
+        Ok(Name { id, files })
+    }
+
+    async fn open(
+        &mut self,
+        id: u32,
+        filename: String,
+        pflags: OpenFlags,
+        _: FileAttributes,
+    ) -> Result<Handle, Self::Error> {
+        let name = Self::file_name(&filename).ok_or(StatusCode::NoSuchFile)?;
+        let writing = pflags.contains(OpenFlags::WRITE);
+
+        // Only the existing sprites can be written, and only to replace them.
+        if self.disk_path(name).is_none() {
+            return Err(if writing {
+                StatusCode::PermissionDenied
+            } else {
+                StatusCode::NoSuchFile
+            });
+        }
+        let name = name.to_string();
+
+        if writing {
+            if self.open_uploads.len() >= MAX_OPEN_UPLOADS {
+                return Err(StatusCode::Failure);
+            }
+            let handle = self.new_handle();
+            self.open_uploads.insert(handle.clone(), (name, Vec::new()));
+            return Ok(Handle { id, handle });
+        }
+
+        let data = self.contents(&name)?;
+        let handle = self.new_handle();
+        self.open_files.insert(handle.clone(), data);
+        Ok(Handle { id, handle })
+    }
+
+    async fn read(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        len: u32,
+    ) -> Result<Data, Self::Error> {
+        let data = self.open_files.get(&handle).ok_or(StatusCode::Failure)?;
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        if start >= data.len() {
+            return Err(StatusCode::Eof);
+        }
+        let end = data.len().min(start.saturating_add(len as usize));
+        Ok(Data {
+            id,
+            data: data[start..end].to_vec(),
+        })
+    }
+
+    async fn close(&mut self, id: u32, handle: String) -> Result<Status, Self::Error> {
+        self.open_files.remove(&handle);
+        self.open_dirs.remove(&handle);
+        if let Some((name, data)) = self.open_uploads.remove(&handle) {
+            return Ok(self.finish_upload(id, name, data));
+        }
+        Ok(Self::status(id, StatusCode::Ok, "Ok"))
+    }
+
+    async fn write(
+        &mut self,
+        id: u32,
+        handle: String,
+        offset: u64,
+        data: Vec<u8>,
+    ) -> Result<Status, Self::Error> {
+        let (_, upload) = self
+            .open_uploads
+            .get_mut(&handle)
+            .ok_or(StatusCode::Failure)?;
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        let end = start.saturating_add(data.len());
+        if end > MAX_UPLOAD_BYTES {
+            let message = format!("uploads are limited to {} KiB", MAX_UPLOAD_BYTES / 1024);
+            return Ok(Self::status(id, StatusCode::Failure, &message));
+        }
+
+        if upload.len() < end {
+            upload.resize(end, 0);
+        }
+        upload[start..end].copy_from_slice(&data);
+        Ok(Self::status(id, StatusCode::Ok, "Ok"))
+    }
+
+    // Nothing can be added to or taken out of the tree.
+    async fn remove(&mut self, _: u32, _: String) -> Result<Status, Self::Error> {
+        Err(StatusCode::PermissionDenied)
+    }
+
+    async fn mkdir(&mut self, _: u32, _: String, _: FileAttributes) -> Result<Status, Self::Error> {
+        Err(StatusCode::PermissionDenied)
+    }
+
+    async fn rename(&mut self, _: u32, _: String, _: String) -> Result<Status, Self::Error> {
+        Err(StatusCode::PermissionDenied)
+    }
+}
@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+
+use image::Rgb;
+
+use crate::caps::{CUBE_LEVELS, nearest_cube_level};
+
+// Sixel terminals are good for at least this many color registers.
+const MAX_COLORS: usize = 256;
+
+// Encodes a `width` x `height` image as a sixel sequence, each pixel blown up to a
+// `scale` x `scale` square. Pixels `pixel` returns None for are left untouched.
+pub fn encode(
+    width: u32,
+    height: u32,
+    scale: u32,
+    pixel: impl Fn(u32, u32) -> Option<Rgb<u8>>,
+) -> Vec<u8> {
+    let (palette, indices) = build_palette(width, height, pixel);
+    let out_width = width * scale;
+    let out_height = height * scale;
+
+    // P2=1 keeps what's under pixels we don't set.
+    let mut out = format!("\x1bP0;1;0q\"1;1;{out_width};{out_height}").into_bytes();
+    for (i, color) in palette.iter().enumerate() {
+        let percent = |channel: u8| u32::from(channel) * 100 / 255;
+        out.extend(
+            format!(
+                "#{i};2;{};{};{}",
+                percent(color[0]),
+                percent(color[1]),
+                percent(color[2])
+            )
+            .bytes(),
+        );
+    }
+
+    let index_at = |x: u32, y: u32| indices[(y / scale * width + x / scale) as usize];
+    for band in (0..out_height).step_by(6) {
+        let rows = band..(band + 6).min(out_height);
+        let mut used: Vec<usize> = (0..out_width)
+            .flat_map(|x| rows.clone().filter_map(move |y| index_at(x, y)))
+            .collect();
+        used.sort_unstable();
+        used.dedup();
+
+        for (n, &color) in used.iter().enumerate() {
+            if n > 0 {
+                // Back to the start of the band for the next color.
+                out.push(b'$');
+            }
+            out.extend(format!("#{color}").bytes());
+            let sixels = (0..out_width).map(|x| {
+                let bits = rows
+                    .clone()
+                    .enumerate()
+                    .filter(|&(_, y)| index_at(x, y) == Some(color))
+                    .fold(0u8, |bits, (bit, _)| bits | (1 << bit));
+                b'?' + bits
+            });
+            push_run_length(&mut out, sixels);
+        }
+        out.push(b'-');
+    }
+
+    out.extend_from_slice(b"\x1b\\");
+    out
+}
+
+// The colors the image uses, and each pixel's index into them. Anything with more
+// colors than there are registers is brought down to xterm's 6x6x6 cube first.
+fn build_palette(
+    width: u32,
+    height: u32,
+    pixel: impl Fn(u32, u32) -> Option<Rgb<u8>>,
+) -> (Vec<Rgb<u8>>, Vec<Option<usize>>) {
+    let pixels: Vec<Option<Rgb<u8>>> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| pixel(x, y))
+        .collect();
+
+    let distinct = pixels
+        .iter()
+        .flatten()
+        .collect::<std::collections::HashSet<_>>();
+    let reduce = distinct.len() > MAX_COLORS;
+
+    let mut palette = Vec::new();
+    let mut registers = HashMap::new();
+    let indices = pixels
+        .into_iter()
+        .map(|pixel| {
+            let mut color = pixel?;
+            if reduce {
+                color = Rgb(color
+                    .0
+                    .map(|channel| CUBE_LEVELS[nearest_cube_level(channel)]));
+            }
+            Some(*registers.entry(color).or_insert_with(|| {
+                palette.push(color);
+                palette.len() - 1
+            }))
+        })
+        .collect();
+    (palette, indices)
+}
+
+// Writes sixel characters, folding runs of four or more into `!count` form.
+fn push_run_length(out: &mut Vec<u8>, sixels: impl Iterator<Item = u8>) {
+    let mut run: Option<(u8, usize)> = None;
+    let flush = |out: &mut Vec<u8>, (sixel, count): (u8, usize)| {
+        if count >= 4 {
+            out.extend(format!("!{count}").bytes());
+            out.push(sixel);
+        } else {
+            out.extend(std::iter::repeat_n(sixel, count));
+        }
+    };
+
+    for sixel in sixels {
+        run = match run {
+            Some((current, count)) if current == sixel => Some((current, count + 1)),
+            Some(previous) => {
+                flush(out, previous);
+                Some((sixel, 1))
+            }
+            None => Some((sixel, 1)),
+        };
+    }
+    if let Some(last) = run {
+        flush(out, last);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb<u8> = Rgb([255, 0, 0]);
+    const BLUE: Rgb<u8> = Rgb([0, 0, 255]);
+
+    fn sixels(
+        width: u32,
+        height: u32,
+        scale: u32,
+        pixel: impl Fn(u32, u32) -> Option<Rgb<u8>>,
+    ) -> String {
+        String::from_utf8(encode(width, height, scale, pixel)).unwrap()
+    }
+
+    #[test]
+    fn encodes_a_pixel() {
+        assert_eq!(
+            sixels(1, 1, 1, |_, _| Some(RED)),
+            "\x1bP0;1;0q\"1;1;1;1#0;2;100;0;0#0@-\x1b\\"
+        );
+        // Scaled up to 2x2, so two columns with the top two bits set.
+        assert_eq!(
+            sixels(1, 1, 2, |_, _| Some(RED)),
+            "\x1bP0;1;0q\"1;1;2;2#0;2;100;0;0#0BB-\x1b\\"
+        );
+    }
+
+    #[test]
+    fn leaves_missing_pixels_unset() {
+        assert_eq!(sixels(2, 1, 1, |_, _| None), "\x1bP0;1;0q\"1;1;2;1-\x1b\\");
+        assert!(sixels(2, 1, 1, |x, _| (x == 1).then_some(RED)).ends_with("#0?@-\x1b\\"));
+    }
+
+    #[test]
+    fn draws_each_color_over_the_band() {
+        let out = sixels(2, 1, 1, |x, _| Some(if x == 0 { RED } else { BLUE }));
+        assert!(out.contains("#0;2;100;0;0#1;2;0;0;100"));
+        assert!(out.ends_with("#0@?$#1?@-\x1b\\"));
+    }
+
+    #[test]
+    fn splits_tall_images_into_bands() {
+        let out = sixels(1, 7, 1, |_, _| Some(RED));
+        assert!(out.ends_with("#0~-#0@-\x1b\\"));
+    }
+
+    #[test]
+    fn folds_runs() {
+        assert!(sixels(5, 1, 1, |_, _| Some(RED)).ends_with("#0!5@-\x1b\\"));
+        assert!(sixels(3, 1, 1, |_, _| Some(RED)).ends_with("#0@@@-\x1b\\"));
+    }
+
+    #[test]
+    fn brings_too_many_colors_down_to_the_cube() {
+        let gradient = |x: u32, y: u32| Some(Rgb([x as u8, y as u8, 100]));
+        let (palette, indices) = build_palette(20, 20, gradient);
+        assert!(palette.len() <= MAX_COLORS);
+        assert!(
+            palette
+                .iter()
+                .all(|color| color.0.iter().all(|c| CUBE_LEVELS.contains(c)))
+        );
+        assert_eq!(palette[indices[0].unwrap()], Rgb([0, 0, 95]));
+
+        let (palette, _) = build_palette(2, 1, |x, _| Some(Rgb([x as u8 + 1, 2, 3])));
+        assert_eq!(palette, [Rgb([1, 2, 3]), Rgb([2, 2, 3])]);
+    }
+}
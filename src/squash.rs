@@ -0,0 +1,73 @@
+use std::f64::consts::PI;
+use std::time::Duration;
+
+// How long a squash and the stretch after it take, the share of that spent squashing,
+// and how far each goes at its most, as a share of robert's size.
+const SQUASH_TIME: Duration = Duration::from_millis(240);
+const SQUASH_SHARE: f64 = 0.4;
+const SQUASH: f64 = 0.3;
+const STRETCH: f64 = 0.15;
+
+// Robert flattening against a wall he's hit and then stretching back off it. He keeps
+// the side that hit where it is, and his area stays the same.
+#[derive(Clone, Copy, Debug)]
+pub struct Squash {
+    // A side wall, as opposed to the top or bottom.
+    side_wall: bool,
+    // Which of his sides hit: the left or top one, or the right or bottom one.
+    near_side: bool,
+    elapsed: Duration,
+}
+
+impl Squash {
+    pub fn new(side_wall: bool, near_side: bool) -> Self {
+        Self {
+            side_wall,
+            near_side,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    // Moves it on by `dt`, returning false once it's over.
+    pub fn update(&mut self, dt: Duration) -> bool {
+        self.elapsed += dt;
+        self.elapsed < SQUASH_TIME
+    }
+
+    // How many times his size he is across and down, right now.
+    fn scales(&self) -> (f64, f64) {
+        let t = (self.elapsed.as_secs_f64() / SQUASH_TIME.as_secs_f64()).min(1.0);
+        let change = if t < SQUASH_SHARE {
+            -SQUASH * (PI * t / SQUASH_SHARE).sin()
+        } else {
+            STRETCH * (PI * (t - SQUASH_SHARE) / (1.0 - SQUASH_SHARE)).sin()
+        };
+        let (along, across) = (1.0 + change, 1.0 / (1.0 + change));
+        if self.side_wall {
+            (along, across)
+        } else {
+            (across, along)
+        }
+    }
+
+    // Passes `place` the points that cover the sprite pixel at `pixel` once squashed,
+    // for a sprite `size` across and down. Pixels are a point across and half a point
+    // down, as in a PixelMap, and stretched ones get enough points to leave no gaps.
+    pub fn cover(&self, pixel: (f64, f64), size: (f64, f64), mut place: impl FnMut(f64, f64)) {
+        let (scale_x, scale_y) = self.scales();
+        let anchor = |side_wall: bool, length: f64| match (side_wall, self.near_side) {
+            (false, _) => length / 2.0,
+            (true, true) => 0.0,
+            (true, false) => length,
+        };
+        let anchor_x = anchor(self.side_wall, size.0);
+        let anchor_y = anchor(!self.side_wall, size.1);
+        let left = anchor_x + (pixel.0 - anchor_x) * scale_x;
+        let top = anchor_y + (pixel.1 - anchor_y) * scale_y;
+        for i in 0..scale_x.ceil().max(1.0) as u32 {
+            for j in 0..scale_y.ceil().max(1.0) as u32 {
+                place(left + f64::from(i), top + f64::from(j) * 0.5);
+            }
+        }
+    }
+}
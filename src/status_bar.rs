@@ -0,0 +1,43 @@
+use std::time::Instant;
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Style},
+    text::Line,
+    widgets::Paragraph,
+};
+
+use crate::admin::format_duration;
+use crate::registry::SessionRegistry;
+
+const KEYS: &str = "q quit  f fps  b braille  s stats";
+
+// The line under robert: how many people are watching, how long the server has been
+// up, and the keys.
+pub struct StatusBar {
+    registry: SessionRegistry,
+    server_started_at: Instant,
+}
+
+impl StatusBar {
+    pub fn new(registry: SessionRegistry, server_started_at: Instant) -> Self {
+        Self {
+            registry,
+            server_started_at,
+        }
+    }
+
+    pub fn draw(&self, frame: &mut Frame, area: Rect) {
+        let text = format!(
+            " {} online | up {} | {KEYS}",
+            self.registry.list().len(),
+            format_duration(self.server_started_at.elapsed().as_secs())
+        );
+        frame.render_widget(
+            Paragraph::new(Line::from(text))
+                .style(Style::default().fg(Color::Black).bg(Color::Gray)),
+            area,
+        );
+    }
+}
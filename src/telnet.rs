@@ -0,0 +1,252 @@
+use tokio::io::AsyncReadExt;
+use tokio::net::tcp::OwnedReadHalf;
+
+use crate::input::{InputEvent, InputSource};
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const ECHO: u8 = 1;
+const SUPPRESS_GO_AHEAD: u8 = 3;
+const NAWS: u8 = 31;
+
+// Sent as soon as a client connects: we do the echoing (so the client doesn't) and skip
+// go-aheads, which together put it in character mode, and we want its window size.
+pub const NEGOTIATION: &[u8] = &[
+    IAC,
+    WILL,
+    ECHO,
+    IAC,
+    WILL,
+    SUPPRESS_GO_AHEAD,
+    IAC,
+    DO,
+    SUPPRESS_GO_AHEAD,
+    IAC,
+    DO,
+    NAWS,
+];
+
+#[derive(Default)]
+enum State {
+    #[default]
+    Data,
+    Iac,
+    // Waiting on the option byte after WILL/WONT/DO/DONT.
+    Option,
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+// Splits what a telnet client sends into keystrokes and window size changes. Keeps its
+// place between reads, since a command can be cut in half by the socket.
+#[derive(Default)]
+pub struct Parser {
+    state: State,
+    subnegotiation: Vec<u8>,
+    // Telnet ends lines with CR NUL or CR LF; only the CR is passed on.
+    after_cr: bool,
+}
+
+impl Parser {
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<InputEvent> {
+        let mut events = Vec::new();
+        let mut input = Vec::new();
+
+        for &byte in bytes {
+            match self.state {
+                State::Data => {
+                    if byte == IAC {
+                        self.state = State::Iac;
+                    } else if self.after_cr && (byte == 0 || byte == b'\n') {
+                        self.after_cr = false;
+                    } else {
+                        self.after_cr = byte == b'\r';
+                        input.push(byte);
+                    }
+                }
+                State::Iac => {
+                    self.state = match byte {
+                        // An escaped 0xff data byte.
+                        IAC => {
+                            input.push(IAC);
+                            State::Data
+                        }
+                        WILL | WONT | DO | DONT => State::Option,
+                        SB => {
+                            self.subnegotiation.clear();
+                            State::Subnegotiation
+                        }
+                        // NOP, go-ahead and friends carry nothing we need.
+                        _ => State::Data,
+                    };
+                }
+                // Clients answer our offers; nothing to do with what they say.
+                State::Option => self.state = State::Data,
+                State::Subnegotiation => {
+                    if byte == IAC {
+                        self.state = State::SubnegotiationIac;
+                    } else if self.subnegotiation.len() < 64 {
+                        self.subnegotiation.push(byte);
+                    }
+                }
+                State::SubnegotiationIac => {
+                    if byte == SE {
+                        if let Some(event) = self.finish_subnegotiation() {
+                            if !input.is_empty() {
+                                events.push(InputEvent::Keys(std::mem::take(&mut input)));
+                            }
+                            events.push(event);
+                        }
+                        self.state = State::Data;
+                    } else {
+                        // IAC IAC inside a subnegotiation is an escaped 0xff.
+                        self.subnegotiation.push(byte);
+                        self.state = State::Subnegotiation;
+                    }
+                }
+            }
+        }
+
+        if !input.is_empty() {
+            events.push(InputEvent::Keys(input));
+        }
+        events
+    }
+
+    fn finish_subnegotiation(&self) -> Option<InputEvent> {
+        match self.subnegotiation.as_slice() {
+            [NAWS, w1, w2, h1, h2] => Some(InputEvent::Resize(
+                u16::from_be_bytes([*w1, *w2]),
+                u16::from_be_bytes([*h1, *h2]),
+            )),
+            _ => None,
+        }
+    }
+}
+
+// Doubles any 0xff in outgoing data so the client doesn't read it as a command.
+pub fn escape(data: &[u8]) -> Vec<u8> {
+    let mut escaped = Vec::with_capacity(data.len());
+    for &byte in data {
+        if byte == IAC {
+            escaped.push(IAC);
+        }
+        escaped.push(byte);
+    }
+    escaped
+}
+
+// A telnet client's side of the connection, read through a `Parser`.
+pub struct Reader {
+    reader: OwnedReadHalf,
+    parser: Parser,
+    buf: [u8; 1024],
+}
+
+impl Reader {
+    pub fn new(reader: OwnedReadHalf) -> Self {
+        Self {
+            reader,
+            parser: Parser::default(),
+            buf: [0; 1024],
+        }
+    }
+}
+
+impl InputSource for Reader {
+    async fn read(&mut self) -> Option<Vec<InputEvent>> {
+        let n = self
+            .reader
+            .read(&mut self.buf)
+            .await
+            .ok()
+            .filter(|n| *n > 0)?;
+        Some(self.parser.feed(&self.buf[..n]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(bytes: &[u8]) -> InputEvent {
+        InputEvent::Keys(bytes.to_vec())
+    }
+
+    #[test]
+    fn passes_keys_through_and_drops_commands() {
+        let mut parser = Parser::default();
+        assert_eq!(parser.feed(b"abc"), [keys(b"abc")]);
+        assert_eq!(
+            parser.feed(&[b'a', IAC, WILL, NAWS, IAC, DO, ECHO, IAC, 241, b'b']),
+            [keys(b"ab")]
+        );
+        assert_eq!(parser.feed(&[IAC, IAC]), [keys(&[IAC])]);
+        assert_eq!(parser.feed(&[IAC, WONT, ECHO]), []);
+    }
+
+    #[test]
+    fn passes_only_the_cr_of_a_line_ending() {
+        let mut parser = Parser::default();
+        assert_eq!(parser.feed(b"a\r\0b\r\nc"), [keys(b"a\rb\rc")]);
+        assert_eq!(parser.feed(b"\r"), [keys(b"\r")]);
+        assert_eq!(parser.feed(b"\n"), []);
+        assert_eq!(parser.feed(b"\n"), [keys(b"\n")]);
+    }
+
+    #[test]
+    fn reads_window_sizes() {
+        let mut parser = Parser::default();
+        assert_eq!(
+            parser.feed(&[b'x', IAC, SB, NAWS, 0, 120, 0, 40, IAC, SE, b'y']),
+            [keys(b"x"), InputEvent::Resize(120, 40), keys(b"y")]
+        );
+        // A 255-wide window has its size byte escaped.
+        assert_eq!(
+            parser.feed(&[IAC, SB, NAWS, 0, IAC, IAC, 0, 50, IAC, SE]),
+            [InputEvent::Resize(255, 50)]
+        );
+    }
+
+    #[test]
+    fn keeps_its_place_across_reads() {
+        let mut parser = Parser::default();
+        let command = [IAC, SB, NAWS, 1, 0, 0, 30, IAC, SE];
+        let mut events = Vec::new();
+        for byte in command {
+            events.extend(parser.feed(&[byte]));
+        }
+        assert_eq!(events, [InputEvent::Resize(256, 30)]);
+
+        assert_eq!(parser.feed(&[IAC]), []);
+        assert_eq!(parser.feed(&[DO]), []);
+        assert_eq!(parser.feed(&[NAWS, b'k']), [keys(b"k")]);
+    }
+
+    #[test]
+    fn ignores_malformed_subnegotiations() {
+        let mut parser = Parser::default();
+        // Too short, an option we didn't ask for, and one that never ends in time.
+        assert_eq!(
+            parser.feed(&[IAC, SB, NAWS, 0, 80, IAC, SE, b'a']),
+            [keys(b"a")]
+        );
+        assert_eq!(parser.feed(&[IAC, SB, 24, 0, b'x', IAC, SE]), []);
+        let mut endless = vec![IAC, SB, NAWS];
+        endless.extend([0; 200]);
+        assert_eq!(parser.feed(&endless), []);
+        assert_eq!(parser.feed(&[IAC, SE, b'b']), [keys(b"b")]);
+    }
+
+    #[test]
+    fn escapes_outgoing_0xff() {
+        assert_eq!(escape(&[1, IAC, 2]), [1, IAC, IAC, 2]);
+        assert_eq!(escape(b"plain"), b"plain");
+    }
+}
@@ -0,0 +1,97 @@
+use futures_util::StreamExt;
+use futures_util::stream::{SplitSink, SplitStream};
+use serde::Deserialize;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+use crate::input::{InputEvent, InputSource};
+
+// xterm.js, pointed back at `/ws` on whatever host served it.
+const PAGE: &str = include_str!("websocket.html");
+// Requests are small; anything bigger than this isn't a browser loading the page.
+const MAX_REQUEST_LEN: usize = 8192;
+
+pub type Sink = SplitSink<WebSocketStream<TcpStream>, Message>;
+
+// Sent by the page as a text message whenever its terminal changes size.
+#[derive(Deserialize)]
+struct Resize {
+    cols: u16,
+    rows: u16,
+}
+
+// The browser's side of the socket: keystrokes arrive as binary messages.
+pub struct Reader(SplitStream<WebSocketStream<TcpStream>>);
+
+impl InputSource for Reader {
+    async fn read(&mut self) -> Option<Vec<InputEvent>> {
+        let event = match self.0.next().await?.ok()? {
+            Message::Binary(data) => InputEvent::Keys(data.to_vec()),
+            Message::Text(text) => match serde_json::from_str(&text) {
+                Ok(Resize { cols, rows }) => InputEvent::Resize(cols, rows),
+                Err(_) => return Some(Vec::new()),
+            },
+            Message::Close(_) => return None,
+            // Pings are answered by tungstenite itself.
+            _ => return Some(Vec::new()),
+        };
+        Some(vec![event])
+    }
+}
+
+// Answers a connection to the web port. Requests for `/ws` are upgraded and handed
+// back along with the browser's User-Agent; anything else gets the page (or a 404)
+// and None.
+// The error type of the handshake callback is tungstenite's to pick, not ours.
+#[allow(clippy::result_large_err)]
+pub async fn accept(
+    stream: TcpStream,
+) -> Result<Option<(Sink, Reader, Option<String>)>, anyhow::Error> {
+    let mut start = [0u8; 8];
+    let n = stream.peek(&mut start).await?;
+    if &start[..n] != b"GET /ws " {
+        serve_page(stream).await?;
+        return Ok(None);
+    }
+
+    let mut user_agent = None;
+    let websocket =
+        tokio_tungstenite::accept_hdr_async(stream, |request: &Request, response: Response| {
+            user_agent = request
+                .headers()
+                .get("user-agent")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            Ok(response)
+        })
+        .await?;
+    let (sink, stream) = websocket.split();
+    Ok(Some((sink, Reader(stream), user_agent)))
+}
+
+async fn serve_page(mut stream: TcpStream) -> Result<(), anyhow::Error> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 || request.len() > MAX_REQUEST_LEN {
+            return Ok(());
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let response = if request.starts_with(b"GET / ") {
+        format!(
+            "HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{PAGE}",
+            PAGE.len()
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}